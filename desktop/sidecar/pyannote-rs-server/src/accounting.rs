@@ -0,0 +1,234 @@
+use std::ops::AddAssign;
+
+use serde::Serialize;
+
+use crate::track::Track;
+
+/// Where received audio went. The millisecond fields partition
+/// `received_ms`: every received millisecond is either covered by a returned
+/// track or counted under exactly one reason for not being covered.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct AudioAccounting {
+    pub received_ms: i64,
+    /// Inside at least one returned track, unattributed ones included.
+    pub covered_ms: i64,
+    /// Segmentation found no speech.
+    pub silence_ms: i64,
+    /// Speech dropped because no speaker could be assigned.
+    pub unassigned_ms: i64,
+    /// Speech removed by post-processing, e.g. a `min_duration` step.
+    pub postprocess_ms: i64,
+    /// Audio of windows that failed or were cancelled. Always 0 in a single
+    /// response, since a failed window has none.
+    pub failed_ms: i64,
+    /// Speech a range deletion cut from the session timeline. Always 0 in a
+    /// single response.
+    pub deleted_ms: i64,
+    /// Segments the segmentation model failed to produce. Their extent is
+    /// unknown, so their audio is counted as silence.
+    pub segment_errors: usize,
+}
+
+/// Length of `sample_count` samples in whole milliseconds. Received audio is
+/// always measured this way, never by the span a client declares, so that
+/// processed and failed windows add up alike.
+pub fn audio_ms(sample_count: usize, sample_rate: u32) -> i64 {
+    ((sample_count as f64 / sample_rate as f64) * 1000.0).round() as i64
+}
+
+impl AudioAccounting {
+    /// Accounts for the `[start_ms, end_ms)` span of a processed window,
+    /// which should be as long as its audio, see [`audio_ms`].
+    /// `voiced` and `unassigned` are the spans segmentation found and the
+    /// subset dropped without a speaker; `tracks` is what was returned.
+    pub fn processed(
+        (start_ms, end_ms): (i64, i64),
+        voiced: &[(i64, i64)],
+        unassigned: &[(i64, i64)],
+        tracks: &[Track],
+        segment_errors: usize,
+    ) -> Self {
+        let received = [(start_ms, end_ms)];
+        let covered = union(
+            tracks
+                .iter()
+                .map(|track| (track.start_ms, track.end_ms))
+                .collect(),
+        );
+        let uncovered = subtract(&received, &covered);
+        let uncovered_voiced = intersect(&uncovered, &union(voiced.to_vec()));
+        let uncovered_unassigned = intersect(&uncovered_voiced, &union(unassigned.to_vec()));
+
+        let received_ms = length(&received);
+        let uncovered_ms = length(&uncovered);
+        let silence_ms = uncovered_ms - length(&uncovered_voiced);
+        let unassigned_ms = length(&uncovered_unassigned);
+        Self {
+            received_ms,
+            covered_ms: received_ms - uncovered_ms,
+            silence_ms,
+            unassigned_ms,
+            postprocess_ms: uncovered_ms - silence_ms - unassigned_ms,
+            failed_ms: 0,
+            deleted_ms: 0,
+            segment_errors,
+        }
+    }
+
+    pub fn failed(received_ms: i64) -> Self {
+        Self {
+            received_ms,
+            failed_ms: received_ms,
+            ..Self::default()
+        }
+    }
+
+    /// Moves the speech `timeline` holds inside `[from_ms, to_ms)` from
+    /// `covered_ms` to `deleted_ms`. Call with the timeline before the range
+    /// is cut from it.
+    pub fn delete_range(&mut self, timeline: &[Track], (from_ms, to_ms): (i64, i64)) {
+        let spoken = union(
+            timeline
+                .iter()
+                .map(|track| (track.start_ms, track.end_ms))
+                .collect(),
+        );
+        let deleted_ms = length(&intersect(&[(from_ms, to_ms)], &spoken)).min(self.covered_ms);
+        self.covered_ms -= deleted_ms;
+        self.deleted_ms += deleted_ms;
+    }
+}
+
+impl AddAssign for AudioAccounting {
+    fn add_assign(&mut self, other: Self) {
+        self.received_ms += other.received_ms;
+        self.covered_ms += other.covered_ms;
+        self.silence_ms += other.silence_ms;
+        self.unassigned_ms += other.unassigned_ms;
+        self.postprocess_ms += other.postprocess_ms;
+        self.failed_ms += other.failed_ms;
+        self.deleted_ms += other.deleted_ms;
+        self.segment_errors += other.segment_errors;
+    }
+}
+
+/// Sorts and merges spans, dropping empty ones.
+fn union(mut spans: Vec<(i64, i64)>) -> Vec<(i64, i64)> {
+    spans.retain(|(start, end)| end > start);
+    spans.sort_unstable();
+    let mut merged: Vec<(i64, i64)> = Vec::with_capacity(spans.len());
+    for (start, end) in spans {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+/// `spans` minus `holes`, both sorted and merged.
+fn subtract(spans: &[(i64, i64)], holes: &[(i64, i64)]) -> Vec<(i64, i64)> {
+    let mut remaining = Vec::new();
+    for &(start, end) in spans {
+        let mut cursor = start;
+        for &(hole_start, hole_end) in holes {
+            if hole_end <= cursor || hole_start >= end {
+                continue;
+            }
+            if hole_start > cursor {
+                remaining.push((cursor, hole_start));
+            }
+            cursor = cursor.max(hole_end);
+        }
+        if cursor < end {
+            remaining.push((cursor, end));
+        }
+    }
+    remaining
+}
+
+fn intersect(spans: &[(i64, i64)], other: &[(i64, i64)]) -> Vec<(i64, i64)> {
+    subtract(spans, &subtract(spans, other))
+}
+
+fn length(spans: &[(i64, i64)]) -> i64 {
+    spans.iter().map(|(start, end)| end - start).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn processed_window_partitions_received_audio() {
+        // Speech at 1000..3000, of which 2500..3000 found no speaker; the
+        // returned track is 1000..2200, the rest lost to post-processing.
+        let audio = AudioAccounting::processed(
+            (0, 4000),
+            &[(1000, 3000)],
+            &[(2500, 3000)],
+            &[Track::test("edge_spk_1", 1000, 2200)],
+            0,
+        );
+        assert_eq!(audio.received_ms, 4000);
+        assert_eq!(audio.covered_ms, 1200);
+        assert_eq!(audio.silence_ms, 2000);
+        assert_eq!(audio.unassigned_ms, 500);
+        assert_eq!(audio.postprocess_ms, 300);
+    }
+
+    #[test]
+    fn tracks_outside_the_audio_are_not_counted() {
+        let audio = AudioAccounting::processed(
+            (1000, 2000),
+            &[(500, 2500)],
+            &[],
+            &[Track::test("edge_spk_1", 500, 2500)],
+            0,
+        );
+        assert_eq!(audio.received_ms, 1000);
+        assert_eq!(audio.covered_ms, 1000);
+    }
+
+    #[test]
+    fn failed_and_processed_windows_measure_audio_alike() {
+        let received_ms = audio_ms(48_008, 16_000);
+        assert_eq!(received_ms, 3001);
+
+        let mut total = AudioAccounting::failed(received_ms);
+        total += AudioAccounting::processed((0, received_ms), &[], &[], &[], 1);
+        assert_eq!(total.received_ms, 6002);
+        assert_eq!(total.failed_ms, 3001);
+        assert_eq!(total.silence_ms, 3001);
+        assert_eq!(total.segment_errors, 1);
+    }
+
+    #[test]
+    fn range_deletion_moves_covered_speech() {
+        let mut audio = AudioAccounting::processed(
+            (0, 4000),
+            &[(0, 4000)],
+            &[],
+            &[
+                Track::test("edge_spk_1", 0, 2000),
+                Track::test("edge_spk_2", 1500, 3000),
+            ],
+            0,
+        );
+        let timeline = [
+            Track::test("edge_spk_1", 0, 2000),
+            Track::test("edge_spk_2", 1500, 3000),
+        ];
+        audio.delete_range(&timeline, (1000, 5000));
+        assert_eq!(audio.covered_ms, 1000);
+        assert_eq!(audio.deleted_ms, 2000);
+        assert_eq!(
+            audio.covered_ms
+                + audio.silence_ms
+                + audio.unassigned_ms
+                + audio.postprocess_ms
+                + audio.deleted_ms,
+            audio.received_ms
+        );
+    }
+}
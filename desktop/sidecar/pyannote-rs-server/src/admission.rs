@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// Per remote address connection limits, for deployments reachable beyond
+/// loopback. A limit of 0 disables it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConnectionLimits {
+    /// Connections one address may hold open at once.
+    pub max_open: usize,
+    /// Sustained new connections per second one address may open.
+    pub rate_per_sec: f64,
+    /// New connections one address may open in a burst above the rate.
+    pub burst: f64,
+}
+
+impl ConnectionLimits {
+    fn enabled(&self) -> bool {
+        self.max_open > 0 || self.rate_per_sec > 0.0
+    }
+}
+
+#[derive(Debug)]
+struct Peer {
+    open: usize,
+    tokens: f64,
+    refilled_at: Instant,
+    /// Set once a rejection has been logged, so a peer stuck in a reconnect
+    /// loop is reported once per episode instead of once per attempt.
+    rejecting: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejection {
+    TooManyOpen,
+    RateExceeded,
+}
+
+impl std::fmt::Display for Rejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TooManyOpen => write!(f, "too many open connections"),
+            Self::RateExceeded => write!(f, "new connection rate exceeded"),
+        }
+    }
+}
+
+/// Token-bucket admission of new connections per remote address, plus a cap
+/// on how many each address keeps open.
+#[derive(Debug, Clone)]
+pub struct ConnectionLimiter {
+    limits: ConnectionLimits,
+    peers: Arc<Mutex<HashMap<IpAddr, Peer>>>,
+}
+
+/// Held for the lifetime of an admitted connection; releases its slot when
+/// dropped.
+#[derive(Debug)]
+pub struct Admission {
+    ip: IpAddr,
+    peers: Option<Arc<Mutex<HashMap<IpAddr, Peer>>>>,
+}
+
+impl Drop for Admission {
+    fn drop(&mut self) {
+        let Some(peers) = &self.peers else {
+            return;
+        };
+        let mut peers = peers
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(peer) = peers.get_mut(&self.ip) {
+            peer.open = peer.open.saturating_sub(1);
+        }
+    }
+}
+
+impl ConnectionLimiter {
+    pub fn new(limits: ConnectionLimits) -> Self {
+        Self {
+            limits,
+            peers: Arc::default(),
+        }
+    }
+
+    /// Admits a connection from `ip`, or says why not. The error carries
+    /// whether this is the first rejection since `ip` was last admitted.
+    pub fn admit(&self, ip: IpAddr) -> Result<Admission, (Rejection, bool)> {
+        if !self.limits.enabled() {
+            return Ok(Admission { ip, peers: None });
+        }
+
+        let now = Instant::now();
+        let burst = self.limits.burst.max(1.0);
+        let mut peers = self
+            .peers
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let peer = peers.entry(ip).or_insert_with(|| Peer {
+            open: 0,
+            tokens: burst,
+            refilled_at: now,
+            rejecting: false,
+        });
+
+        if self.limits.rate_per_sec > 0.0 {
+            let elapsed = now.duration_since(peer.refilled_at).as_secs_f64();
+            peer.tokens = (peer.tokens + elapsed * self.limits.rate_per_sec).min(burst);
+            peer.refilled_at = now;
+        }
+
+        let rejection = if self.limits.max_open > 0 && peer.open >= self.limits.max_open {
+            Some(Rejection::TooManyOpen)
+        } else if self.limits.rate_per_sec > 0.0 && peer.tokens < 1.0 {
+            Some(Rejection::RateExceeded)
+        } else {
+            None
+        };
+        if let Some(rejection) = rejection {
+            let first = !peer.rejecting;
+            peer.rejecting = true;
+            return Err((rejection, first));
+        }
+
+        if self.limits.rate_per_sec > 0.0 {
+            peer.tokens -= 1.0;
+        }
+        peer.open += 1;
+        peer.rejecting = false;
+        Ok(Admission {
+            ip,
+            peers: Some(self.peers.clone()),
+        })
+    }
+
+    /// Forgets addresses with nothing open and a full bucket, which would be
+    /// recreated identically on their next connection.
+    pub fn prune(&self) {
+        if !self.limits.enabled() {
+            return;
+        }
+        let now = Instant::now();
+        let burst = self.limits.burst.max(1.0);
+        let rate = self.limits.rate_per_sec;
+        let mut peers = self
+            .peers
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        peers.retain(|_, peer| {
+            let tokens = if rate > 0.0 {
+                peer.tokens + now.duration_since(peer.refilled_at).as_secs_f64() * rate
+            } else {
+                burst
+            };
+            peer.open > 0 || tokens < burst
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+    use std::time::Duration;
+
+    const PEER: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+    const OTHER: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2));
+
+    fn limiter(max_open: usize, rate_per_sec: f64, burst: f64) -> ConnectionLimiter {
+        ConnectionLimiter::new(ConnectionLimits {
+            max_open,
+            rate_per_sec,
+            burst,
+        })
+    }
+
+    #[test]
+    fn disabled_limits_admit_everything() {
+        let limiter = limiter(0, 0.0, 0.0);
+        let admitted: Vec<_> = (0..100).map(|_| limiter.admit(PEER).unwrap()).collect();
+        assert_eq!(admitted.len(), 100);
+        assert!(limiter.peers.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn open_connections_are_capped_per_address() {
+        let limiter = limiter(2, 0.0, 0.0);
+        let first = limiter.admit(PEER).unwrap();
+        let _second = limiter.admit(PEER).unwrap();
+        assert_eq!(
+            limiter.admit(PEER).unwrap_err(),
+            (Rejection::TooManyOpen, true)
+        );
+        assert_eq!(
+            limiter.admit(PEER).unwrap_err(),
+            (Rejection::TooManyOpen, false)
+        );
+        assert!(limiter.admit(OTHER).is_ok());
+
+        drop(first);
+        assert!(limiter.admit(PEER).is_ok());
+    }
+
+    #[test]
+    fn bucket_allows_a_burst_then_refills() {
+        let limiter = limiter(0, 200.0, 2.0);
+        assert!(limiter.admit(PEER).is_ok());
+        assert!(limiter.admit(PEER).is_ok());
+        assert_eq!(
+            limiter.admit(PEER).unwrap_err(),
+            (Rejection::RateExceeded, true)
+        );
+
+        // 200 per second refills a token every 5ms.
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(limiter.admit(PEER).is_ok());
+        assert!(limiter.admit(PEER).is_ok());
+        // The burst caps the refill, however long the wait was.
+        assert!(limiter.admit(PEER).is_err());
+    }
+
+    #[test]
+    fn prune_forgets_idle_addresses_only() {
+        let limiter = limiter(4, 200.0, 2.0);
+        let held = limiter.admit(PEER).unwrap();
+        drop(limiter.admit(OTHER).unwrap());
+
+        std::thread::sleep(Duration::from_millis(20));
+        limiter.prune();
+        let peers: Vec<IpAddr> = limiter.peers.lock().unwrap().keys().copied().collect();
+        assert_eq!(peers, [PEER]);
+
+        drop(held);
+        limiter.prune();
+        assert!(limiter.peers.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,433 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::track::Track;
+
+/// Bounds of `talk_ratio` windows and steps: one second to one day.
+pub const MIN_TALK_RATIO_MS: i64 = 1_000;
+pub const MAX_TALK_RATIO_MS: i64 = 24 * 60 * 60 * 1_000;
+/// Upper bound on the windows a single talk-ratio request may produce.
+pub const MAX_TALK_RATIO_BUCKETS: i64 = 10_000;
+
+/// The timeline would need more than [`MAX_TALK_RATIO_BUCKETS`] windows at
+/// the requested step.
+#[derive(Debug)]
+pub struct TooManyBuckets {
+    pub buckets: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Turn {
+    pub speaker_id: String,
+    pub start_ms: i64,
+    pub end_ms: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Exchange {
+    pub question_speaker_id: String,
+    pub answer_speaker_id: String,
+    pub question_start_ms: i64,
+    pub question_end_ms: i64,
+    pub answer_start_ms: i64,
+    pub answer_end_ms: i64,
+    pub answer_latency_ms: i64,
+    pub answer_duration_ms: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LatencyStats {
+    pub count: usize,
+    pub p50_ms: i64,
+    pub p90_ms: i64,
+    pub max_ms: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AnalyticsResponse {
+    pub session_id: String,
+    pub interviewer_speaker_id: Option<String>,
+    pub turn_count: usize,
+    pub exchanges: Vec<Exchange>,
+    pub turn_latency: BTreeMap<String, LatencyStats>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TalkRatioBucket {
+    pub start_ms: i64,
+    pub end_ms: i64,
+    pub speech_ms: i64,
+    pub speaker_ms: BTreeMap<String, i64>,
+    pub speaker_share: BTreeMap<String, f64>,
+    pub dominant_speaker_id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TalkRatioResponse {
+    pub session_id: String,
+    pub window_ms: i64,
+    pub step_ms: i64,
+    pub buckets: Vec<TalkRatioBucket>,
+}
+
+/// Collapses a start-sorted timeline into floor turns: consecutive tracks of
+/// the same speaker form one turn regardless of the pause between them.
+pub fn build_turns(timeline: &[Track]) -> Vec<Turn> {
+    let mut sorted: Vec<&Track> = timeline.iter().collect();
+    sorted.sort_by(|a, b| a.start_ms.cmp(&b.start_ms).then(a.end_ms.cmp(&b.end_ms)));
+
+    let mut turns: Vec<Turn> = Vec::new();
+    for track in sorted {
+        if let Some(last) = turns.last_mut() {
+            if last.speaker_id == track.speaker_id {
+                last.end_ms = last.end_ms.max(track.end_ms);
+                continue;
+            }
+        }
+        turns.push(Turn {
+            speaker_id: track.speaker_id.clone(),
+            start_ms: track.start_ms,
+            end_ms: track.end_ms,
+        });
+    }
+    turns
+}
+
+/// An exchange is an interviewer turn immediately followed by a turn from
+/// anyone else. Latency is negative when the answer overlaps the question.
+pub fn detect_exchanges(turns: &[Turn], interviewer: &str) -> Vec<Exchange> {
+    turns
+        .windows(2)
+        .filter(|pair| pair[0].speaker_id == interviewer && pair[1].speaker_id != interviewer)
+        .map(|pair| {
+            let (question, answer) = (&pair[0], &pair[1]);
+            Exchange {
+                question_speaker_id: question.speaker_id.clone(),
+                answer_speaker_id: answer.speaker_id.clone(),
+                question_start_ms: question.start_ms,
+                question_end_ms: question.end_ms,
+                answer_start_ms: answer.start_ms,
+                answer_end_ms: answer.end_ms,
+                answer_latency_ms: answer.start_ms - question.end_ms,
+                answer_duration_ms: (answer.end_ms - answer.start_ms).max(0),
+            }
+        })
+        .collect()
+}
+
+/// Nearest-rank percentile of ascending `sorted`: the smallest value with at
+/// least `pct` percent of the values at or below it. 0 when there are none.
+fn percentile(sorted: &[i64], pct: f64) -> i64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = ((pct / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Floor-taking latency per speaker: the gap between the previous turn's end
+/// and the start of each turn they take. Overlapping takeovers count as
+/// negative latency, which keeps interruptions visible in the distribution.
+pub fn turn_latency(turns: &[Turn]) -> BTreeMap<String, LatencyStats> {
+    let mut latencies: BTreeMap<String, Vec<i64>> = BTreeMap::new();
+    for pair in turns.windows(2) {
+        latencies
+            .entry(pair[1].speaker_id.clone())
+            .or_default()
+            .push(pair[1].start_ms - pair[0].end_ms);
+    }
+
+    latencies
+        .into_iter()
+        .map(|(speaker_id, mut values)| {
+            values.sort_unstable();
+            let stats = LatencyStats {
+                count: values.len(),
+                p50_ms: percentile(&values, 50.0),
+                p90_ms: percentile(&values, 90.0),
+                max_ms: values[values.len() - 1],
+            };
+            (speaker_id, stats)
+        })
+        .collect()
+}
+
+/// Without an explicit interviewer the first speaker of the session is
+/// assumed to be the one asking questions.
+pub fn analyze(
+    session_id: &str,
+    timeline: &[Track],
+    interviewer: Option<&str>,
+) -> AnalyticsResponse {
+    let turns = build_turns(timeline);
+    let interviewer_speaker_id = interviewer
+        .map(str::to_string)
+        .or_else(|| turns.first().map(|turn| turn.speaker_id.clone()));
+
+    let exchanges = match interviewer_speaker_id.as_deref() {
+        Some(interviewer) => detect_exchanges(&turns, interviewer),
+        None => Vec::new(),
+    };
+
+    AnalyticsResponse {
+        session_id: session_id.to_string(),
+        interviewer_speaker_id,
+        turn_count: turns.len(),
+        exchanges,
+        turn_latency: turn_latency(&turns),
+    }
+}
+
+/// Speaking share per speaker over windows of `window_ms` advanced by
+/// `step_ms`, starting at the first track. Shares are relative to the summed
+/// speech inside each window, so silent stretches do not dilute them.
+pub fn talk_ratio(
+    session_id: &str,
+    timeline: &[Track],
+    window_ms: i64,
+    step_ms: i64,
+) -> Result<TalkRatioResponse, TooManyBuckets> {
+    let step_ms = step_ms.max(1);
+    let timeline_start_ms = timeline
+        .iter()
+        .map(|track| track.start_ms)
+        .min()
+        .unwrap_or(0);
+    let timeline_end_ms = timeline.iter().map(|track| track.end_ms).max().unwrap_or(0);
+    let span_ms = timeline_end_ms.saturating_sub(timeline_start_ms).max(0);
+    let bucket_count = span_ms / step_ms + i64::from(span_ms % step_ms != 0);
+    if bucket_count > MAX_TALK_RATIO_BUCKETS {
+        return Err(TooManyBuckets {
+            buckets: bucket_count,
+        });
+    }
+    let mut buckets = Vec::with_capacity(bucket_count as usize);
+
+    let mut start_ms = timeline_start_ms;
+    while start_ms < timeline_end_ms {
+        let end_ms = start_ms.saturating_add(window_ms);
+        let mut speaker_ms: BTreeMap<String, i64> = BTreeMap::new();
+        for track in timeline {
+            let overlap_ms = track.end_ms.min(end_ms) - track.start_ms.max(start_ms);
+            if overlap_ms > 0 {
+                *speaker_ms.entry(track.speaker_id.clone()).or_default() += overlap_ms;
+            }
+        }
+
+        let speech_ms: i64 = speaker_ms.values().sum();
+        let speaker_share = speaker_ms
+            .iter()
+            .map(|(speaker_id, ms)| (speaker_id.clone(), *ms as f64 / speech_ms as f64))
+            .collect();
+        let dominant_speaker_id = speaker_ms
+            .iter()
+            .max_by_key(|(_, ms)| **ms)
+            .map(|(speaker_id, _)| speaker_id.clone());
+
+        buckets.push(TalkRatioBucket {
+            start_ms,
+            end_ms,
+            speech_ms,
+            speaker_ms,
+            speaker_share,
+            dominant_speaker_id,
+        });
+        let Some(next_ms) = start_ms.checked_add(step_ms) else {
+            break;
+        };
+        start_ms = next_ms;
+    }
+
+    Ok(TalkRatioResponse {
+        session_id: session_id.to_string(),
+        window_ms,
+        step_ms,
+        buckets,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shares_are_relative_to_speech_in_each_window() {
+        let timeline = [
+            Track::test("edge_spk_1", 0, 3000),
+            Track::test("edge_spk_2", 3000, 4000),
+            Track::test("edge_spk_2", 6000, 8000),
+        ];
+        let ratio = talk_ratio("s", &timeline, 4000, 4000).unwrap();
+        assert_eq!(ratio.buckets.len(), 2);
+        assert_eq!(ratio.buckets[0].speaker_share["edge_spk_1"], 0.75);
+        assert_eq!(
+            ratio.buckets[0].dominant_speaker_id.as_deref(),
+            Some("edge_spk_1")
+        );
+        assert_eq!(ratio.buckets[1].speech_ms, 2000);
+        assert_eq!(ratio.buckets[1].speaker_share["edge_spk_2"], 1.0);
+    }
+
+    #[test]
+    fn extreme_windows_do_not_overflow() {
+        let timeline = [Track::test("edge_spk_1", i64::MAX - 10, i64::MAX - 1)];
+        let ratio = talk_ratio("s", &timeline, i64::MAX, i64::MAX).unwrap();
+        assert_eq!(ratio.buckets.len(), 1);
+        assert_eq!(ratio.buckets[0].end_ms, i64::MAX);
+    }
+
+    #[test]
+    fn windows_start_at_the_first_track() {
+        let timeline = [Track::test("edge_spk_1", 3_600_000, 3_605_000)];
+        let ratio = talk_ratio("s", &timeline, 4000, 4000).unwrap();
+        assert_eq!(ratio.buckets.len(), 2);
+        assert_eq!(ratio.buckets[0].start_ms, 3_600_000);
+        assert_eq!(ratio.buckets[1].speech_ms, 1000);
+    }
+
+    #[test]
+    fn window_count_is_capped() {
+        let step_ms = 1000;
+        let end_ms = MAX_TALK_RATIO_BUCKETS * step_ms;
+        let timeline = [Track::test("edge_spk_1", 0, end_ms)];
+        assert_eq!(
+            talk_ratio("s", &timeline, step_ms, step_ms)
+                .unwrap()
+                .buckets
+                .len() as i64,
+            MAX_TALK_RATIO_BUCKETS
+        );
+
+        let timeline = [Track::test("edge_spk_1", 0, end_ms + 1)];
+        let error = talk_ratio("s", &timeline, step_ms, step_ms).unwrap_err();
+        assert_eq!(error.buckets, MAX_TALK_RATIO_BUCKETS + 1);
+    }
+
+    fn turn(speaker_id: &str, start_ms: i64, end_ms: i64) -> Turn {
+        Turn {
+            speaker_id: speaker_id.to_string(),
+            start_ms,
+            end_ms,
+        }
+    }
+
+    fn spans(turns: &[Turn]) -> Vec<(&str, i64, i64)> {
+        turns
+            .iter()
+            .map(|turn| (turn.speaker_id.as_str(), turn.start_ms, turn.end_ms))
+            .collect()
+    }
+
+    #[test]
+    fn consecutive_tracks_of_a_speaker_form_one_turn() {
+        let timeline = [
+            Track::test("edge_spk_2", 5000, 6000),
+            Track::test("edge_spk_1", 0, 1000),
+            Track::test("edge_spk_1", 4000, 4500),
+            Track::test("edge_spk_1", 1000, 4200),
+            Track::test("edge_spk_1", 9000, 9500),
+        ];
+        assert_eq!(
+            spans(&build_turns(&timeline)),
+            [
+                ("edge_spk_1", 0, 4500),
+                ("edge_spk_2", 5000, 6000),
+                ("edge_spk_1", 9000, 9500),
+            ]
+        );
+        assert!(build_turns(&[]).is_empty());
+    }
+
+    #[test]
+    fn exchanges_pair_interviewer_turns_with_the_next_reply() {
+        let turns = [
+            turn("edge_spk_1", 0, 2000),
+            turn("edge_spk_2", 2500, 6000),
+            turn("edge_spk_1", 5800, 7000),
+            turn("edge_spk_3", 7000, 8000),
+            turn("edge_spk_2", 8200, 9000),
+        ];
+        let exchanges = detect_exchanges(&turns, "edge_spk_1");
+        assert_eq!(exchanges.len(), 2);
+        assert_eq!(exchanges[0].answer_speaker_id, "edge_spk_2");
+        assert_eq!(exchanges[0].answer_latency_ms, 500);
+        assert_eq!(exchanges[0].answer_duration_ms, 3500);
+        assert_eq!(exchanges[1].answer_speaker_id, "edge_spk_3");
+        assert_eq!(exchanges[1].answer_latency_ms, 0);
+
+        // Seen from another interviewer the same turns pair up differently,
+        // and an overlapping answer has negative latency.
+        let exchanges = detect_exchanges(&turns, "edge_spk_2");
+        assert_eq!(exchanges.len(), 1);
+        assert_eq!(exchanges[0].question_start_ms, 2500);
+        assert_eq!(exchanges[0].answer_latency_ms, -200);
+    }
+
+    #[test]
+    fn the_first_speaker_is_the_default_interviewer() {
+        let timeline = [
+            Track::test("edge_spk_2", 0, 1000),
+            Track::test("edge_spk_1", 1500, 3000),
+            Track::test("edge_spk_2", 3500, 4000),
+            Track::test("edge_spk_1", 4100, 5000),
+        ];
+        let implied = analyze("s", &timeline, None);
+        assert_eq!(
+            implied.interviewer_speaker_id.as_deref(),
+            Some("edge_spk_2")
+        );
+        assert_eq!(implied.turn_count, 4);
+        assert_eq!(implied.exchanges.len(), 2);
+        assert!(implied
+            .exchanges
+            .iter()
+            .all(|exchange| exchange.question_speaker_id == "edge_spk_2"));
+
+        let explicit = analyze("s", &timeline, Some("edge_spk_1"));
+        assert_eq!(explicit.exchanges.len(), 1);
+        assert_eq!(explicit.exchanges[0].answer_start_ms, 3500);
+
+        assert!(analyze("s", &[], None).interviewer_speaker_id.is_none());
+    }
+
+    #[test]
+    fn percentile_takes_the_nearest_rank() {
+        assert_eq!(percentile(&[], 50.0), 0);
+        assert_eq!(percentile(&[7], 50.0), 7);
+        assert_eq!(percentile(&[7], 90.0), 7);
+
+        let even = [100, 200, 300, 400];
+        assert_eq!(percentile(&even, 0.0), 100);
+        assert_eq!(percentile(&even, 50.0), 200);
+        assert_eq!(percentile(&even, 51.0), 300);
+        assert_eq!(percentile(&even, 90.0), 400);
+        assert_eq!(percentile(&even, 100.0), 400);
+    }
+
+    #[test]
+    fn latency_counts_overlapping_takeovers_as_negative() {
+        let turns = [
+            turn("edge_spk_1", 0, 2000),
+            turn("edge_spk_2", 1500, 3000),
+            turn("edge_spk_1", 3400, 5000),
+            turn("edge_spk_2", 5000, 6000),
+            turn("edge_spk_1", 5900, 7000),
+        ];
+        let latency = turn_latency(&turns);
+        assert_eq!(latency.len(), 2);
+
+        let first = &latency["edge_spk_1"];
+        assert_eq!(first.count, 2);
+        assert_eq!(first.p50_ms, -100);
+        assert_eq!(first.p90_ms, 400);
+        assert_eq!(first.max_ms, 400);
+
+        let second = &latency["edge_spk_2"];
+        assert_eq!(second.count, 2);
+        assert_eq!(second.p50_ms, -500);
+        assert_eq!(second.max_ms, 0);
+
+        assert!(turn_latency(&turns[..1]).is_empty());
+    }
+}
@@ -0,0 +1,160 @@
+/// Maximum-weight matching of rows to columns by the Hungarian algorithm
+/// (Kuhn-Munkres with potentials, cubic in the larger side). Returns the
+/// matched column of each row; rows left over when there are more rows than
+/// columns, or only matchable at weight 0, get `None`.
+pub fn maximize(weights: &[Vec<i64>]) -> Vec<Option<usize>> {
+    let rows = weights.len();
+    let cols = weights.iter().map(Vec::len).max().unwrap_or(0);
+    let n = rows.max(cols);
+    if n == 0 {
+        return Vec::new();
+    }
+
+    // Square cost matrix, 1-indexed as in the textbook formulation; padding
+    // cells have weight 0.
+    let weight = |row: usize, col: usize| {
+        weights
+            .get(row)
+            .and_then(|cells| cells.get(col))
+            .copied()
+            .unwrap_or(0)
+    };
+    let max_weight = weights.iter().flatten().copied().max().unwrap_or(0).max(0);
+    let cost = |row: usize, col: usize| max_weight - weight(row - 1, col - 1);
+
+    let mut row_potential = vec![0i64; n + 1];
+    let mut col_potential = vec![0i64; n + 1];
+    // Row matched to each column, 0 for none.
+    let mut row_of_col = vec![0usize; n + 1];
+    let mut previous_col = vec![0usize; n + 1];
+
+    for row in 1..=n {
+        row_of_col[0] = row;
+        let mut col = 0;
+        let mut min_slack = vec![i64::MAX; n + 1];
+        let mut visited = vec![false; n + 1];
+        loop {
+            visited[col] = true;
+            let current_row = row_of_col[col];
+            let mut delta = i64::MAX;
+            let mut next_col = 0;
+            for candidate in 1..=n {
+                if visited[candidate] {
+                    continue;
+                }
+                let slack = cost(current_row, candidate)
+                    - row_potential[current_row]
+                    - col_potential[candidate];
+                if slack < min_slack[candidate] {
+                    min_slack[candidate] = slack;
+                    previous_col[candidate] = col;
+                }
+                if min_slack[candidate] < delta {
+                    delta = min_slack[candidate];
+                    next_col = candidate;
+                }
+            }
+            for candidate in 0..=n {
+                if visited[candidate] {
+                    row_potential[row_of_col[candidate]] += delta;
+                    col_potential[candidate] -= delta;
+                } else {
+                    min_slack[candidate] -= delta;
+                }
+            }
+            col = next_col;
+            if row_of_col[col] == 0 {
+                break;
+            }
+        }
+        // Flip the augmenting path.
+        while col != 0 {
+            let previous = previous_col[col];
+            row_of_col[col] = row_of_col[previous];
+            col = previous;
+        }
+    }
+
+    let mut matched = vec![None; rows];
+    for (col, &row) in row_of_col.iter().enumerate().take(cols + 1).skip(1) {
+        if row != 0 && row <= rows && weight(row - 1, col - 1) > 0 {
+            matched[row - 1] = Some(col - 1);
+        }
+    }
+    matched
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn total(weights: &[Vec<i64>], matching: &[Option<usize>]) -> i64 {
+        matching
+            .iter()
+            .enumerate()
+            .filter_map(|(row, col)| Some(weights[row][(*col)?]))
+            .sum()
+    }
+
+    /// Best total over every injective assignment of rows to columns.
+    fn brute_force(weights: &[Vec<i64>], row: usize, used: &mut Vec<bool>) -> i64 {
+        if row == weights.len() {
+            return 0;
+        }
+        let mut best = brute_force(weights, row + 1, used);
+        for col in 0..used.len() {
+            if !used[col] {
+                used[col] = true;
+                best = best.max(weights[row][col] + brute_force(weights, row + 1, used));
+                used[col] = false;
+            }
+        }
+        best
+    }
+
+    #[test]
+    fn prefers_the_best_total_over_greedy_choices() {
+        // Greedy takes 9 for row 0 and ends with 9 + 1; the optimum is 8 + 8.
+        let weights = vec![vec![9, 8], vec![8, 1]];
+        assert_eq!(maximize(&weights), [Some(1), Some(0)]);
+    }
+
+    #[test]
+    fn matches_brute_force_on_rectangular_inputs() {
+        let mut seed = 7u64;
+        for (rows, cols) in [(3, 3), (2, 4), (4, 2), (5, 5), (1, 3), (3, 1)] {
+            for _ in 0..20 {
+                let weights: Vec<Vec<i64>> = (0..rows)
+                    .map(|_| {
+                        (0..cols)
+                            .map(|_| {
+                                seed = seed
+                                    .wrapping_mul(6364136223846793005)
+                                    .wrapping_add(1442695040888963407);
+                                ((seed >> 33) % 10) as i64
+                            })
+                            .collect()
+                    })
+                    .collect();
+                let matching = maximize(&weights);
+                assert_eq!(matching.len(), rows);
+                let mut cols_used: Vec<usize> = matching.iter().flatten().copied().collect();
+                cols_used.sort_unstable();
+                cols_used.dedup();
+                assert_eq!(cols_used.len(), matching.iter().flatten().count());
+                assert_eq!(
+                    total(&weights, &matching),
+                    brute_force(&weights, 0, &mut vec![false; cols]),
+                    "{weights:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn zero_weight_rows_stay_unmatched() {
+        let weights = vec![vec![0, 0], vec![0, 5], vec![3, 0]];
+        assert_eq!(maximize(&weights), [None, Some(1), Some(0)]);
+        assert_eq!(maximize(&[]), Vec::<Option<usize>>::new());
+    }
+}
@@ -0,0 +1,145 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use axum::body::HttpBody;
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::header;
+use axum::middleware::Next;
+use axum::response::Response;
+use serde::Serialize;
+
+use crate::clock::current_epoch_ms;
+
+/// One audit line. Only request metadata is recorded; bodies, and with them
+/// any audio, never reach the log.
+#[derive(Debug, Serialize)]
+struct AuditRecord<'a> {
+    ts_ms: i64,
+    remote_addr: Option<String>,
+    client_id: Option<&'a str>,
+    user_agent: Option<&'a str>,
+    method: &'a str,
+    path: &'a str,
+    request_bytes: Option<u64>,
+    response_bytes: Option<u64>,
+    status: u16,
+    duration_ms: u128,
+}
+
+struct AuditFile {
+    file: File,
+    written: u64,
+}
+
+/// Append-only JSON-lines log with size-based rotation: once `max_bytes` is
+/// reached the file moves to `<path>.1`, older generations shift up, and
+/// anything beyond `max_files` is deleted.
+pub struct AuditLog {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: usize,
+    current: Mutex<AuditFile>,
+}
+
+fn open_append(path: &Path) -> std::io::Result<AuditFile> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let written = file.metadata()?.len();
+    Ok(AuditFile { file, written })
+}
+
+fn rotated_path(path: &Path, generation: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{generation}"));
+    PathBuf::from(name)
+}
+
+impl AuditLog {
+    pub fn open(path: PathBuf, max_bytes: u64, max_files: usize) -> std::io::Result<Self> {
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            std::fs::create_dir_all(parent)?;
+        }
+        let current = open_append(&path)?;
+        Ok(Self {
+            path,
+            max_bytes: max_bytes.max(1),
+            max_files: max_files.max(1),
+            current: Mutex::new(current),
+        })
+    }
+
+    fn rotate(&self, current: &mut AuditFile) -> std::io::Result<()> {
+        let _ = std::fs::remove_file(rotated_path(&self.path, self.max_files));
+        for generation in (1..self.max_files).rev() {
+            let from = rotated_path(&self.path, generation);
+            if from.exists() {
+                std::fs::rename(&from, rotated_path(&self.path, generation + 1))?;
+            }
+        }
+        std::fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        *current = open_append(&self.path)?;
+        Ok(())
+    }
+
+    fn append(&self, record: &AuditRecord<'_>) -> std::io::Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+
+        let mut current = self
+            .current
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if current.written > 0 && current.written + line.len() as u64 > self.max_bytes {
+            self.rotate(&mut current)?;
+        }
+        current.file.write_all(&line)?;
+        current.written += line.len() as u64;
+        Ok(())
+    }
+}
+
+pub async fn record(State(log): State<Arc<AuditLog>>, request: Request, next: Next) -> Response {
+    let started_at = Instant::now();
+    let method = request.method().to_string();
+    let path = request.uri().path().to_string();
+    let remote_addr = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.to_string());
+    let headers = request.headers();
+    let header_value = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    };
+    let client_id = header_value("x-client-id");
+    let user_agent = header_value(header::USER_AGENT.as_str());
+    let request_bytes: Option<u64> =
+        header_value(header::CONTENT_LENGTH.as_str()).and_then(|value| value.parse().ok());
+
+    let response = next.run(request).await;
+
+    let record = AuditRecord {
+        ts_ms: current_epoch_ms(),
+        remote_addr,
+        client_id: client_id.as_deref(),
+        user_agent: user_agent.as_deref(),
+        method: &method,
+        path: &path,
+        request_bytes,
+        response_bytes: response.body().size_hint().exact(),
+        status: response.status().as_u16(),
+        duration_ms: started_at.elapsed().as_millis(),
+    };
+    if let Err(error) = log.append(&record) {
+        eprintln!("pyannote-rs sidecar audit log write failed: {error}");
+    }
+    response
+}
@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::scoring::Scorer;
+use crate::speakers::SpeakerRegistry;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CurvePoint {
+    pub threshold: f32,
+    pub predicted_speakers: usize,
+    pub purity: f32,
+    pub coverage: f32,
+    pub f1: f32,
+}
+
+/// For each group in `outer`, counts the members carrying its most common
+/// `inner` value, as a fraction of all items. Purity when `outer` is the
+/// predicted clustering, coverage when it is the reference labels.
+fn majority_fraction(outer: &[usize], inner: &[usize]) -> f32 {
+    let mut counts: HashMap<usize, HashMap<usize, usize>> = HashMap::new();
+    for (&o, &i) in outer.iter().zip(inner) {
+        *counts.entry(o).or_default().entry(i).or_default() += 1;
+    }
+    let majority: usize = counts
+        .values()
+        .map(|inner_counts| inner_counts.values().copied().max().unwrap_or(0))
+        .sum();
+    majority as f32 / outer.len().max(1) as f32
+}
+
+/// Replays the streaming assignment at one threshold: snippets are fed in
+/// order through a fresh registry exactly as live windows would be.
+pub fn evaluate(
+    embeddings: &[Vec<f32>],
+    labels: &[usize],
+    threshold: f32,
+    max_speakers: usize,
+    scorer: &Scorer,
+) -> CurvePoint {
+    let mut registry = SpeakerRegistry::new(max_speakers);
+    let predicted: Vec<usize> = embeddings
+        .iter()
+        .map(|embedding| {
+            registry
+                .search_speaker(embedding.clone(), threshold, scorer)
+                .or_else(|| registry.best_speaker_match(embedding, scorer))
+                .unwrap_or(0)
+        })
+        .collect();
+
+    let purity = majority_fraction(&predicted, labels);
+    let coverage = majority_fraction(labels, &predicted);
+    let f1 = if purity + coverage > 0.0 {
+        2.0 * purity * coverage / (purity + coverage)
+    } else {
+        0.0
+    };
+    let mut distinct = predicted.clone();
+    distinct.sort_unstable();
+    distinct.dedup();
+
+    CurvePoint {
+        threshold,
+        predicted_speakers: distinct.len(),
+        purity,
+        coverage,
+        f1,
+    }
+}
+
+/// Sweeps `[from, to]` in `step` increments. Ties on F1 resolve to the lowest
+/// threshold, which errs on the side of merging rather than splitting.
+pub fn sweep(
+    embeddings: &[Vec<f32>],
+    labels: &[usize],
+    (from, to, step): (f32, f32, f32),
+    max_speakers: usize,
+    scorer: &Scorer,
+) -> Vec<CurvePoint> {
+    let steps = ((to - from) / step).floor() as usize;
+    (0..=steps)
+        .map(|index| {
+            let threshold = (from + step * index as f32).min(to);
+            evaluate(embeddings, labels, threshold, max_speakers, scorer)
+        })
+        .collect()
+}
+
+pub fn best_point(curve: &[CurvePoint]) -> Option<&CurvePoint> {
+    curve
+        .iter()
+        .fold(None, |best: Option<&CurvePoint>, point| match best {
+            Some(current) if current.f1 >= point.f1 => Some(current),
+            _ => Some(point),
+        })
+}
@@ -0,0 +1,405 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use clap::{ArgAction, Args, Parser, Subcommand};
+
+use crate::handlers::offline::{diarize_recording, OfflineOptions};
+use crate::models::ModelSet;
+use crate::profile::Profiler;
+use crate::scoring::Scorer;
+use crate::{accounting, analytics, gating, jobs, postprocess, report, snapshot, storage};
+
+#[derive(Parser)]
+#[command(name = "pyannote-rs")]
+#[command(about = "pyannote-rs HTTP sidecar for speaker diarization", long_about = None)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Runs the HTTP sidecar.
+    Serve(ServeArgs),
+    /// Diarizes a WAV recording (pcm_s16le, mono) from the command line.
+    Offline(OfflineArgs),
+}
+
+#[derive(Args, Clone)]
+struct OfflineArgs {
+    /// WAV recording to diarize.
+    input: PathBuf,
+
+    #[arg(long)]
+    segmentation_model: Option<PathBuf>,
+
+    #[arg(long)]
+    embedding_model: Option<PathBuf>,
+
+    #[arg(long, default_value_t = 8)]
+    max_speakers: usize,
+
+    #[arg(long, default_value_t = 0.52)]
+    threshold: f32,
+
+    #[arg(long)]
+    scoring_config: Option<PathBuf>,
+
+    #[arg(long)]
+    postprocess_config: Option<PathBuf>,
+
+    #[arg(long, default_value_t = 0)]
+    min_embedding_ms: u64,
+
+    #[arg(long, default_value_t = 0.0)]
+    min_embedding_hz: f64,
+
+    /// Writes the full report bundle (timeline JSON, RTTM, analytics JSON,
+    /// per-speaker CSV) into this directory instead of printing the timeline.
+    #[arg(long)]
+    report_dir: Option<PathBuf>,
+}
+
+/// Options of the `serve` command, which are also the configuration of an
+/// embedded router: see [`build_state`](crate::build_state) and
+/// [`build_router`](crate::build_router). Start from
+/// `ServeArgs::default()`, the command-line defaults, and set what you need.
+#[derive(Debug, Args, Clone)]
+pub struct ServeArgs {
+    /// Address to listen on. Repeatable; `localhost` binds both `127.0.0.1`
+    /// and `::1`, and `::` binds dual-stack.
+    #[arg(long = "host", default_value = "127.0.0.1")]
+    pub hosts: Vec<String>,
+
+    #[arg(long, default_value_t = 9705)]
+    pub port: u16,
+
+    #[arg(long)]
+    pub segmentation_model: Option<PathBuf>,
+
+    #[arg(long)]
+    pub embedding_model: Option<PathBuf>,
+
+    #[arg(long, default_value_t = 8)]
+    pub max_speakers: usize,
+
+    #[arg(long, default_value_t = 0.52)]
+    pub threshold: f32,
+
+    #[arg(long, default_value_t = 3600)]
+    pub session_ttl_sec: u64,
+
+    #[arg(long, default_value_t = 600)]
+    pub idempotency_ttl_sec: u64,
+
+    /// Retire a session's speaker from matching once they have not spoken
+    /// for this many seconds of session audio, so one-off speakers early in a
+    /// long interview stop attracting segments. Their tracks are kept, and a
+    /// retired speaker who speaks again gets a new id. 0 disables.
+    #[arg(long, default_value_t = 0)]
+    pub retire_silent_speakers_sec: u64,
+
+    /// Seconds without audio before a `session_inactive` event is pushed to
+    /// `/events` subscribers; 0 disables the watcher.
+    #[arg(long, default_value_t = 30)]
+    pub inactivity_warning_sec: u64,
+
+    /// Clock jump, in seconds, treated as the host having slept. Session idle
+    /// timers are extended by the jump and clients get a `clock_jump` warning.
+    #[arg(long, default_value_t = 30)]
+    pub clock_jump_threshold_sec: u64,
+
+    /// Offline recordings diarized concurrently. Further jobs queue by
+    /// priority (`offline` before `backfill`), and all offline work pauses
+    /// while live windows are in flight.
+    #[arg(long, default_value_t = 1)]
+    pub offline_workers: usize,
+
+    /// JSON file selecting the speaker similarity metric (cosine or plda),
+    /// embedding normalization, and PLDA backend parameters.
+    #[arg(long)]
+    pub scoring_config: Option<PathBuf>,
+
+    /// Latency budget as `<endpoint>=<soft_ms>:<hard_ms>`, e.g.
+    /// `diarize=800:2000`. Repeatable; supported endpoints are `diarize` and
+    /// `diarize_offline`.
+    #[arg(long = "latency-budget", value_parser = parse_latency_budget)]
+    pub latency_budgets: Vec<(String, LatencyBudget)>,
+
+    /// JSON file declaring the ordered track post-processing steps (merge,
+    /// min_duration, smoothing, collar, relabel). Defaults to a single merge
+    /// step with a 250 ms gap.
+    #[arg(long)]
+    pub postprocess_config: Option<PathBuf>,
+
+    /// Serve HTTP/1.1 only instead of also accepting cleartext HTTP/2 (h2c).
+    #[arg(long)]
+    pub http1_only: bool,
+
+    #[arg(long, default_value_t = true, action = ArgAction::Set)]
+    pub keep_alive: bool,
+
+    /// Seconds an idle HTTP/1.1 keep-alive connection is held open; 0
+    /// disables. HTTP/2 connections are not timed out when idle; dead peers
+    /// are found with keep-alive pings instead.
+    #[arg(long, default_value_t = 75)]
+    pub idle_timeout_sec: u64,
+
+    #[arg(long, default_value_t = 128)]
+    pub http2_max_concurrent_streams: u32,
+
+    /// Seconds between HTTP/2 keep-alive pings; a connection whose ping goes
+    /// unanswered for 20 seconds is closed. 0 disables.
+    #[arg(long, default_value_t = 20)]
+    pub http2_keep_alive_interval_sec: u64,
+
+    /// Connections one remote address may hold open at once; further ones are
+    /// closed on accept. 0 disables.
+    #[arg(long, default_value_t = 0)]
+    pub max_connections_per_ip: usize,
+
+    /// New connections per second one remote address may open, enforced as
+    /// a token bucket holding `--connection-burst-per-ip`. 0 disables.
+    #[arg(long, default_value_t = 0.0)]
+    pub connection_rate_per_ip: f64,
+
+    #[arg(long, default_value_t = 20.0)]
+    pub connection_burst_per_ip: f64,
+
+    /// Directory for everything the sidecar writes. Defaults to a per-user
+    /// location: `%LOCALAPPDATA%\pyannote-rs` on Windows, `~/Library/Application
+    /// Support/pyannote-rs` on macOS, `$XDG_STATE_HOME/pyannote-rs` elsewhere.
+    #[arg(long)]
+    pub state_dir: Option<PathBuf>,
+
+    /// Append a JSON-lines audit record (caller, route, sizes, status) for
+    /// every request to this file. Audio payloads are never written. A
+    /// relative path is taken under the state directory.
+    #[arg(long)]
+    pub audit_log: Option<PathBuf>,
+
+    #[arg(long, default_value_t = 10 * 1024 * 1024)]
+    pub audit_log_max_bytes: u64,
+
+    /// Rotated audit files kept next to the active one.
+    #[arg(long, default_value_t = 5)]
+    pub audit_log_max_files: usize,
+
+    /// Free space `/ready` requires on every directory the sidecar writes to.
+    #[arg(long, default_value_t = 512)]
+    pub min_free_disk_mb: u64,
+
+    /// Attach a per-stage timing breakdown to every diarize response. Clients
+    /// can also opt in per request with `"profile": true`.
+    #[arg(long)]
+    pub profile: bool,
+
+    /// Write a Chrome trace file per profiled request into this directory,
+    /// taken under the state directory when relative.
+    #[arg(long)]
+    pub profile_trace_dir: Option<PathBuf>,
+
+    /// Segments with less non-silent audio than this are not embedded but
+    /// take the speaker of the nearest speech within 2 s. 0 disables.
+    #[arg(long, default_value_t = 0)]
+    pub min_embedding_ms: u64,
+
+    /// Segments whose RMS frequency, a rough bandwidth estimate, is below
+    /// this are gated like short ones. 0 disables.
+    #[arg(long, default_value_t = 0.0)]
+    pub min_embedding_hz: f64,
+
+    /// Milliseconds of each session's audio kept and prepended to its next
+    /// window when that window starts where the last one ended, so speech
+    /// straddling the boundary is segmented and embedded whole. Tracks are
+    /// still trimmed to the window. 0 disables.
+    #[arg(long, default_value_t = 0)]
+    pub pre_roll_ms: u64,
+
+    /// Reject unknown JSON fields, out-of-range or non-finite numbers and
+    /// conflicting parameters with a 422 listing each field path, instead of
+    /// ignoring, clamping or defaulting them.
+    #[arg(long)]
+    pub strict_validation: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LatencyBudget {
+    pub soft: Duration,
+    pub hard: Duration,
+}
+
+fn parse_latency_budget(raw: &str) -> Result<(String, LatencyBudget), String> {
+    let (endpoint, budget) = raw
+        .split_once('=')
+        .ok_or("expected <endpoint>=<soft_ms>:<hard_ms>")?;
+    if !LATENCY_BUDGET_ENDPOINTS.contains(&endpoint) {
+        return Err(format!(
+            "unknown endpoint {endpoint}, expected one of {}",
+            LATENCY_BUDGET_ENDPOINTS.join(", ")
+        ));
+    }
+    let (soft, hard) = budget
+        .split_once(':')
+        .ok_or("expected <soft_ms>:<hard_ms>")?;
+    let soft: u64 = soft
+        .parse()
+        .map_err(|error| format!("invalid soft budget: {error}"))?;
+    let hard: u64 = hard
+        .parse()
+        .map_err(|error| format!("invalid hard budget: {error}"))?;
+    if soft > hard {
+        return Err("soft budget must not exceed hard budget".to_string());
+    }
+    Ok((
+        endpoint.to_string(),
+        LatencyBudget {
+            soft: Duration::from_millis(soft),
+            hard: Duration::from_millis(hard),
+        },
+    ))
+}
+
+const LATENCY_BUDGET_ENDPOINTS: [&str; 2] = ["diarize", "diarize_offline"];
+
+/// Entry point of the `pyannote-rs` binary: parses the command line and runs
+/// the chosen command.
+pub async fn run() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
+
+    match cli.command {
+        Command::Serve(args) => crate::serve(args).await?,
+        Command::Offline(args) => offline(args).await?,
+    }
+
+    Ok(())
+}
+
+pub fn exe_dir() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let exe_path = std::env::current_exe()?;
+    Ok(exe_path
+        .parent()
+        .map(PathBuf::from)
+        .ok_or("cannot resolve binary directory")?)
+}
+
+pub fn resolve_model_path(explicit: Option<PathBuf>, exe_dir: &Path, filename: &str) -> PathBuf {
+    explicit.unwrap_or_else(|| exe_dir.join("models").join(filename))
+}
+
+async fn offline(args: OfflineArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let exe_dir = exe_dir()?;
+    let segmentation_model =
+        resolve_model_path(args.segmentation_model, &exe_dir, "segmentation-3.0.onnx");
+    let embedding_model = resolve_model_path(
+        args.embedding_model,
+        &exe_dir,
+        "wespeaker_en_voxceleb_CAM++.onnx",
+    );
+    let models = ModelSet::load(1, segmentation_model, embedding_model)?;
+
+    let scorer = match &args.scoring_config {
+        Some(path) => Scorer::load(path)?,
+        None => Scorer::cosine(),
+    };
+    let postprocess = match &args.postprocess_config {
+        Some(path) => postprocess::Pipeline::load(path)?,
+        None => postprocess::Pipeline::default(),
+    };
+
+    let (samples, sample_rate) = pyannote_rs::read_wav(&args.input.to_string_lossy())
+        .map_err(|error| format!("failed to read {}: {error}", args.input.display()))?;
+    if sample_rate == 0 {
+        return Err("input has a sample rate of 0".into());
+    }
+    let recording_id = args
+        .input
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| "recording".to_string());
+
+    let options = OfflineOptions {
+        sample_rate,
+        threshold: args.threshold.clamp(0.0, 1.0),
+        max_speakers: args.max_speakers.max(1),
+        budget: None,
+        gate: gating::EmbeddingGate {
+            min_effective_ms: args.min_embedding_ms as i64,
+            min_rms_hz: args.min_embedding_hz,
+        },
+    };
+    let recording = diarize_recording(
+        &scorer,
+        &models,
+        &postprocess,
+        options,
+        &samples,
+        &mut Profiler::new(false),
+        &jobs::Progress::default(),
+    )
+    .await
+    .map_err(|error| error.message)?;
+    let mut recording = recording;
+    postprocess.relabel(&mut recording.tracks);
+
+    for warning in &recording.warnings {
+        eprintln!("pyannote-rs offline: {warning}");
+    }
+
+    let config = snapshot::ConfigSnapshot::offline(&models, &scorer, &postprocess, &options);
+    let timeline = report::TimelineReport {
+        recording_id: &recording_id,
+        sample_rate,
+        duration_ms: accounting::audio_ms(samples.len(), sample_rate),
+        speaker_count: recording.speaker_count,
+        tracks: &recording.tracks,
+        warnings: &recording.warnings,
+        config: &config,
+    };
+    match &args.report_dir {
+        Some(dir) => {
+            let analytics = report::AnalyticsExport {
+                analytics: analytics::analyze(&recording_id, &recording.tracks, None),
+                config: config.clone(),
+            };
+            for path in report::write_bundle(dir, &timeline, &analytics)? {
+                println!("{}", path.display());
+            }
+        }
+        None => println!("{}", serde_json::to_string_pretty(&timeline)?),
+    }
+
+    Ok(())
+}
+
+impl ServeArgs {
+    /// `--state-dir`, or the per-OS default when it is not given.
+    pub fn state_dir(&self) -> PathBuf {
+        self.state_dir
+            .clone()
+            .unwrap_or_else(storage::default_state_dir)
+    }
+
+    pub fn audit_log_path(&self) -> Option<PathBuf> {
+        let state_dir = self.state_dir();
+        self.audit_log
+            .as_deref()
+            .map(|path| storage::resolve(&state_dir, path))
+    }
+
+    pub fn profile_trace_dir(&self) -> Option<PathBuf> {
+        let state_dir = self.state_dir();
+        self.profile_trace_dir
+            .as_deref()
+            .map(|dir| storage::resolve(&state_dir, dir))
+    }
+}
+
+impl Default for ServeArgs {
+    fn default() -> Self {
+        match Cli::parse_from(["pyannote-rs", "serve"]).command {
+            Command::Serve(args) => args,
+            Command::Offline(_) => unreachable!("parsed the serve command"),
+        }
+    }
+}
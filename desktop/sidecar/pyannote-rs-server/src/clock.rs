@@ -0,0 +1,82 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::time::MissedTickBehavior;
+
+use crate::events::ServerEvent;
+use crate::state::ServerState;
+
+const TICK: Duration = Duration::from_secs(1);
+
+pub fn current_epoch_ms() -> i64 {
+    let now = std::time::SystemTime::now();
+    match now.duration_since(std::time::UNIX_EPOCH) {
+        Ok(duration) => duration.as_millis() as i64,
+        Err(_) => 0,
+    }
+}
+
+#[derive(Debug)]
+struct Observation {
+    instant: Instant,
+    epoch_ms: i64,
+}
+
+/// Spots the host having been asleep. Depending on the platform the
+/// monotonic clock either stops during suspend, so wall time outruns it, or
+/// keeps counting, so the regular tick arrives far too late; both show up as
+/// a jump between two observations.
+#[derive(Debug)]
+pub struct ClockMonitor {
+    threshold_ms: i64,
+    last: Mutex<Observation>,
+}
+
+impl ClockMonitor {
+    pub fn new(threshold_ms: i64) -> Self {
+        Self {
+            threshold_ms,
+            last: Mutex::new(Observation {
+                instant: Instant::now(),
+                epoch_ms: current_epoch_ms(),
+            }),
+        }
+    }
+
+    fn observe(&self) -> Option<i64> {
+        let now = Observation {
+            instant: Instant::now(),
+            epoch_ms: current_epoch_ms(),
+        };
+        let mut last = self.last.lock().unwrap();
+        let monotonic_ms = now.instant.duration_since(last.instant).as_millis() as i64;
+        let wall_ms = now.epoch_ms - last.epoch_ms;
+        *last = now;
+
+        let jump_ms = (wall_ms - monotonic_ms).max(monotonic_ms - TICK.as_millis() as i64);
+        (jump_ms >= self.threshold_ms).then_some(jump_ms)
+    }
+}
+
+/// Checks for a clock jump and, if there was one, pauses every session's idle
+/// timer for its length so sleeping through a break does not evict the
+/// interview, then tells `/events` subscribers to re-sync window offsets.
+/// Called before anything that evicts or warns about idle sessions.
+pub fn check(state: &ServerState) {
+    let Some(jump_ms) = state.clock.observe() else {
+        return;
+    };
+    state.sessions.defer_idle(jump_ms);
+    println!("pyannote-rs sidecar detected a {jump_ms}ms clock jump; session idle timers extended");
+    // Sending only fails when nobody is subscribed, which is fine.
+    let _ = state.events.send(ServerEvent::ClockJump { jump_ms });
+}
+
+pub async fn watch(state: Arc<ServerState>) {
+    let mut ticker = tokio::time::interval(TICK);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+        check(&state);
+    }
+}
@@ -0,0 +1,342 @@
+use std::time::Instant;
+
+use crate::scoring::Scorer;
+
+/// Most embeddings clustered pairwise. The similarity matrix holds the
+/// square of this, so larger inputs are clustered from an even subsample and
+/// the rest join the cluster whose centroid they score best against.
+pub const MAX_CLUSTERED: usize = 2000;
+
+/// Clustering stopped because its deadline passed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeadlineExceeded;
+
+pub fn centroid(embeddings: &[&[f32]]) -> Vec<f32> {
+    let dim = embeddings
+        .first()
+        .map(|embedding| embedding.len())
+        .unwrap_or(0);
+    let mut sum = vec![0.0f32; dim];
+    for embedding in embeddings {
+        for (acc, value) in sum.iter_mut().zip(embedding.iter()) {
+            *acc += value;
+        }
+    }
+    let count = embeddings.len().max(1) as f32;
+    sum.iter_mut().for_each(|value| *value /= count);
+    sum
+}
+
+/// Average-linkage agglomerative clustering over pairwise scorer similarity. Merging
+/// continues while the closest pair is at least `threshold` similar, or while
+/// more than `max_clusters` clusters remain. Labels are numbered from 1 in
+/// order of first appearance so they line up with session speaker ids.
+///
+/// Inputs above [`MAX_CLUSTERED`] are subsampled. The deadline is checked
+/// between rows of the similarity matrix and between merges.
+pub fn agglomerative(
+    embeddings: &[Vec<f32>],
+    threshold: f32,
+    max_clusters: usize,
+    scorer: &Scorer,
+    deadline: Option<Instant>,
+) -> Result<Vec<usize>, DeadlineExceeded> {
+    let n = embeddings.len();
+    let stride = n.div_ceil(MAX_CLUSTERED).max(1);
+    if stride == 1 {
+        let clusters = cluster(embeddings, threshold, max_clusters, scorer, deadline)?;
+        return Ok(first_appearance_labels(&clusters));
+    }
+
+    let sample: Vec<Vec<f32>> = embeddings.iter().step_by(stride).cloned().collect();
+    let sampled = cluster(&sample, threshold, max_clusters, scorer, deadline)?;
+    let mut members: Vec<Vec<&[f32]>> = vec![Vec::new(); n];
+    for (embedding, &cluster) in sample.iter().zip(&sampled) {
+        members[cluster].push(embedding);
+    }
+    let centroids: Vec<(usize, Vec<f32>)> = members
+        .iter()
+        .enumerate()
+        .filter(|(_, members)| !members.is_empty())
+        .map(|(cluster, members)| (cluster, centroid(members)))
+        .collect();
+
+    let clusters: Vec<usize> = embeddings
+        .iter()
+        .enumerate()
+        .map(|(index, embedding)| {
+            if index % stride == 0 {
+                return sampled[index / stride];
+            }
+            centroids
+                .iter()
+                .map(|(cluster, centroid)| (*cluster, scorer.score(embedding, centroid)))
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+                .map_or(0, |(cluster, _)| cluster)
+        })
+        .collect();
+    Ok(first_appearance_labels(&clusters))
+}
+
+/// [`agglomerative`] on the blocking pool, so a large session never stalls
+/// the async threads it shares with live windows. The embeddings are handed
+/// back alongside the labels.
+pub async fn agglomerative_blocking(
+    embeddings: Vec<Vec<f32>>,
+    threshold: f32,
+    max_clusters: usize,
+    scorer: Scorer,
+    deadline: Option<Instant>,
+) -> (Vec<Vec<f32>>, Result<Vec<usize>, DeadlineExceeded>) {
+    let clustered = tokio::task::spawn_blocking(move || {
+        let labels = agglomerative(&embeddings, threshold, max_clusters, &scorer, deadline);
+        (embeddings, labels)
+    })
+    .await;
+    match clustered {
+        Ok(clustered) => clustered,
+        Err(error) => std::panic::resume_unwind(error.into_panic()),
+    }
+}
+
+/// Cluster representative of every embedding: the full dendrogram is built
+/// with the nearest-neighbour chain algorithm, valid for average linkage and
+/// quadratic instead of cubic, and its merges are then replayed from the
+/// most similar down until the stopping rule holds.
+fn cluster(
+    embeddings: &[Vec<f32>],
+    threshold: f32,
+    max_clusters: usize,
+    scorer: &Scorer,
+    deadline: Option<Instant>,
+) -> Result<Vec<usize>, DeadlineExceeded> {
+    let expired = || deadline.is_some_and(|deadline| Instant::now() > deadline);
+    let n = embeddings.len();
+
+    let mut similarity = vec![vec![0.0f32; n]; n];
+    for i in 0..n {
+        if expired() {
+            return Err(DeadlineExceeded);
+        }
+        for j in (i + 1)..n {
+            let value = scorer.score(&embeddings[i], &embeddings[j]);
+            similarity[i][j] = value;
+            similarity[j][i] = value;
+        }
+    }
+
+    let mut size = vec![1usize; n];
+    let mut active = vec![true; n];
+    let mut remaining = n;
+    let mut chain: Vec<usize> = Vec::new();
+    let mut merges: Vec<(usize, usize, f32)> = Vec::with_capacity(n.saturating_sub(1));
+    while remaining > 1 {
+        if expired() {
+            return Err(DeadlineExceeded);
+        }
+        if chain.is_empty() {
+            chain.extend(active.iter().position(|&active| active));
+        }
+        let current = chain[chain.len() - 1];
+        let previous = chain.len().checked_sub(2).map(|index| chain[index]);
+
+        // Ties go to the previous link, so the chain cannot cycle.
+        let mut nearest = previous;
+        for other in (0..n).filter(|&other| active[other] && other != current) {
+            if nearest
+                .is_none_or(|nearest| similarity[current][other] > similarity[current][nearest])
+            {
+                nearest = Some(other);
+            }
+        }
+        let Some(nearest) = nearest else {
+            break;
+        };
+        if Some(nearest) != previous {
+            chain.push(nearest);
+            continue;
+        }
+
+        chain.truncate(chain.len() - 2);
+        let (keep, absorb) = (current.min(nearest), current.max(nearest));
+        merges.push((keep, absorb, similarity[keep][absorb]));
+        let keep_size = size[keep] as f32;
+        let absorb_size = size[absorb] as f32;
+        for other in (0..n).filter(|&other| active[other] && other != keep && other != absorb) {
+            let merged = (similarity[keep][other] * keep_size
+                + similarity[absorb][other] * absorb_size)
+                / (keep_size + absorb_size);
+            similarity[keep][other] = merged;
+            similarity[other][keep] = merged;
+        }
+        size[keep] += size[absorb];
+        active[absorb] = false;
+        remaining -= 1;
+    }
+
+    // Average linkage never merges at a higher similarity than a merge below
+    // it, so the stable sort keeps every merge after the ones it depends on.
+    merges.sort_by(|a, b| b.2.total_cmp(&a.2));
+    let mut parent: Vec<usize> = (0..n).collect();
+    let mut clusters = n;
+    for (keep, absorb, value) in merges {
+        if value < threshold && clusters <= max_clusters.max(1) {
+            break;
+        }
+        let (keep, absorb) = (root(&mut parent, keep), root(&mut parent, absorb));
+        parent[absorb] = keep;
+        clusters -= 1;
+    }
+    Ok((0..n).map(|index| root(&mut parent, index)).collect())
+}
+
+fn root(parent: &mut [usize], mut index: usize) -> usize {
+    while parent[index] != index {
+        parent[index] = parent[parent[index]];
+        index = parent[index];
+    }
+    index
+}
+
+/// Numbers clusters from 1 in order of first appearance.
+fn first_appearance_labels(clusters: &[usize]) -> Vec<usize> {
+    let mut label_of_cluster: Vec<Option<usize>> = vec![None; clusters.len()];
+    let mut next_label = 1;
+    clusters
+        .iter()
+        .map(|&cluster| {
+            *label_of_cluster[cluster].get_or_insert_with(|| {
+                let label = next_label;
+                next_label += 1;
+                label
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// Unit vectors near one of `axes` orthogonal directions, deterministic
+    /// per `seed`.
+    fn embeddings(count: usize, axes: usize, seed: u64) -> Vec<Vec<f32>> {
+        let mut state = seed;
+        let mut noise = move || {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            ((state >> 33) as f32 / (1u64 << 31) as f32 - 0.5) * 0.4
+        };
+        (0..count)
+            .map(|index| {
+                let mut embedding: Vec<f32> = (0..8).map(|_| noise()).collect();
+                embedding[index % axes] += 1.0;
+                embedding
+            })
+            .collect()
+    }
+
+    /// The greedy cubic average linkage the chain algorithm replaced.
+    fn naive(embeddings: &[Vec<f32>], threshold: f32, max_clusters: usize) -> Vec<usize> {
+        let scorer = Scorer::cosine();
+        let n = embeddings.len();
+        let mut members: Vec<Vec<usize>> = (0..n).map(|index| vec![index]).collect();
+        let average = |a: &[usize], b: &[usize]| {
+            let total: f32 = a
+                .iter()
+                .flat_map(|&i| b.iter().map(move |&j| (i, j)))
+                .map(|(i, j)| scorer.score(&embeddings[i], &embeddings[j]))
+                .sum();
+            total / (a.len() * b.len()) as f32
+        };
+        while members.len() > 1 {
+            let mut best = (0, 1, f32::NEG_INFINITY);
+            for i in 0..members.len() {
+                for j in (i + 1)..members.len() {
+                    let value = average(&members[i], &members[j]);
+                    if value > best.2 {
+                        best = (i, j, value);
+                    }
+                }
+            }
+            if best.2 < threshold && members.len() <= max_clusters {
+                break;
+            }
+            let absorbed = members.remove(best.1);
+            members[best.0].extend(absorbed);
+        }
+        let mut cluster_of = vec![0; n];
+        for members in &members {
+            for &member in members {
+                cluster_of[member] = members[0];
+            }
+        }
+        first_appearance_labels(&cluster_of)
+    }
+
+    #[test]
+    fn separates_speakers_and_numbers_by_first_appearance() {
+        let embeddings = embeddings(12, 3, 1);
+        let labels = agglomerative(&embeddings, 0.5, 8, &Scorer::cosine(), None).unwrap();
+        assert_eq!(labels, [1, 2, 3, 1, 2, 3, 1, 2, 3, 1, 2, 3]);
+    }
+
+    #[test]
+    fn max_clusters_forces_merges_below_threshold() {
+        let embeddings = embeddings(12, 3, 2);
+        let labels = agglomerative(&embeddings, 0.5, 2, &Scorer::cosine(), None).unwrap();
+        assert_eq!(labels.iter().max(), Some(&2));
+    }
+
+    #[test]
+    fn matches_greedy_average_linkage() {
+        for seed in 0..20 {
+            let embeddings = embeddings(30, 5, seed);
+            for (threshold, max_clusters) in [(0.3, 10), (0.8, 10), (0.99, 4)] {
+                assert_eq!(
+                    agglomerative(
+                        &embeddings,
+                        threshold,
+                        max_clusters,
+                        &Scorer::cosine(),
+                        None
+                    )
+                    .unwrap(),
+                    naive(&embeddings, threshold, max_clusters),
+                    "seed {seed}, threshold {threshold}, max_clusters {max_clusters}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn large_inputs_are_subsampled_and_fully_labeled() {
+        // Three speakers, so the every-other subsample still holds each.
+        let embeddings = embeddings(MAX_CLUSTERED + 1, 3, 3);
+        let labels = agglomerative(&embeddings, 0.5, 8, &Scorer::cosine(), None).unwrap();
+        assert_eq!(labels.len(), embeddings.len());
+        for (index, label) in labels.iter().enumerate() {
+            assert_eq!(*label, index % 3 + 1);
+        }
+    }
+
+    #[test]
+    fn stops_at_the_deadline() {
+        let embeddings = embeddings(50, 2, 4);
+        let deadline = Instant::now() - Duration::from_millis(1);
+        assert_eq!(
+            agglomerative(&embeddings, 0.5, 8, &Scorer::cosine(), Some(deadline)),
+            Err(DeadlineExceeded)
+        );
+    }
+
+    #[test]
+    fn empty_input_has_no_labels() {
+        assert_eq!(
+            agglomerative(&[], 0.5, 8, &Scorer::cosine(), None),
+            Ok(Vec::new())
+        );
+    }
+}
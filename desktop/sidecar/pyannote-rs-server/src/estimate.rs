@@ -0,0 +1,207 @@
+use serde::Serialize;
+
+use crate::scoring::Scorer;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Candidate {
+    pub speakers: usize,
+    /// Share of the eigen-gap mass behind this count; scores sum to 1 unless
+    /// every gap is zero.
+    pub score: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Estimate {
+    pub speaker_count: usize,
+    pub confidence: f64,
+    pub candidates: Vec<Candidate>,
+}
+
+/// Eigenvalues of a symmetric matrix by cyclic Jacobi rotations, ascending.
+/// Cubic per sweep, which is fine for the few hundred segments estimation
+/// works on.
+fn symmetric_eigenvalues(mut matrix: Vec<Vec<f64>>) -> Vec<f64> {
+    let n = matrix.len();
+    for _ in 0..100 {
+        let off_diagonal: f64 = (0..n)
+            .flat_map(|i| ((i + 1)..n).map(move |j| (i, j)))
+            .map(|(i, j)| matrix[i][j] * matrix[i][j])
+            .sum();
+        if off_diagonal < 1e-18 {
+            break;
+        }
+
+        for p in 0..n {
+            for q in (p + 1)..n {
+                if matrix[p][q].abs() < 1e-15 {
+                    continue;
+                }
+                let theta = (matrix[q][q] - matrix[p][p]) / (2.0 * matrix[p][q]);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                let c = 1.0 / (t * t + 1.0).sqrt();
+                let s = t * c;
+
+                for row in matrix.iter_mut() {
+                    let (kp, kq) = (row[p], row[q]);
+                    row[p] = c * kp - s * kq;
+                    row[q] = s * kp + c * kq;
+                }
+                let mut row_p = std::mem::take(&mut matrix[p]);
+                let mut row_q = std::mem::take(&mut matrix[q]);
+                for (pk, qk) in row_p.iter_mut().zip(row_q.iter_mut()) {
+                    (*pk, *qk) = (c * *pk - s * *qk, s * *pk + c * *qk);
+                }
+                matrix[p] = row_p;
+                matrix[q] = row_q;
+            }
+        }
+    }
+
+    let mut eigenvalues: Vec<f64> = (0..n).map(|i| matrix[i][i]).collect();
+    eigenvalues.sort_by(f64::total_cmp);
+    eigenvalues
+}
+
+/// Estimates the number of speakers with the eigen-gap heuristic: on the
+/// normalized Laplacian of the segment affinity graph, `k` well separated
+/// speakers give `k` eigenvalues near zero followed by a jump. Affinities are
+/// scorer similarities clipped to [0, 1]. Each count up to `max_speakers` is
+/// scored by its share of the gaps, and the winning share is the confidence.
+pub fn eigengap(embeddings: &[Vec<f32>], max_speakers: usize, scorer: &Scorer) -> Estimate {
+    let n = embeddings.len();
+    if n < 2 {
+        return Estimate {
+            speaker_count: n,
+            confidence: 0.0,
+            candidates: Vec::new(),
+        };
+    }
+
+    let mut affinity = vec![vec![0.0f64; n]; n];
+    for i in 0..n {
+        for j in (i + 1)..n {
+            let value = (scorer.score(&embeddings[i], &embeddings[j]) as f64).clamp(0.0, 1.0);
+            affinity[i][j] = value;
+            affinity[j][i] = value;
+        }
+    }
+
+    let inv_sqrt_degree: Vec<f64> = affinity
+        .iter()
+        .map(|row| 1.0 / row.iter().sum::<f64>().max(1e-9).sqrt())
+        .collect();
+    let laplacian: Vec<Vec<f64>> = (0..n)
+        .map(|i| {
+            (0..n)
+                .map(|j| {
+                    let identity = if i == j { 1.0 } else { 0.0 };
+                    identity - affinity[i][j] * inv_sqrt_degree[i] * inv_sqrt_degree[j]
+                })
+                .collect()
+        })
+        .collect();
+
+    let eigenvalues = symmetric_eigenvalues(laplacian);
+    let max_speakers = max_speakers.clamp(1, n - 1);
+    let gaps: Vec<f64> = (1..=max_speakers)
+        .map(|k| (eigenvalues[k] - eigenvalues[k - 1]).max(0.0))
+        .collect();
+    let total: f64 = gaps.iter().sum();
+
+    let candidates: Vec<Candidate> = gaps
+        .iter()
+        .enumerate()
+        .map(|(index, gap)| Candidate {
+            speakers: index + 1,
+            score: if total > 0.0 { gap / total } else { 0.0 },
+        })
+        .collect();
+    let best = candidates
+        .iter()
+        .max_by(|a, b| a.score.total_cmp(&b.score))
+        .cloned()
+        .unwrap_or(Candidate {
+            speakers: 1,
+            score: 0.0,
+        });
+
+    Estimate {
+        speaker_count: best.speakers,
+        confidence: best.score,
+        candidates,
+    }
+}
+
+/// [`eigengap`] on the blocking pool, so a large sample never stalls the
+/// async threads.
+pub async fn eigengap_blocking(
+    embeddings: Vec<Vec<f32>>,
+    max_speakers: usize,
+    scorer: Scorer,
+) -> Estimate {
+    let estimated =
+        tokio::task::spawn_blocking(move || eigengap(&embeddings, max_speakers, &scorer)).await;
+    match estimated {
+        Ok(estimate) => estimate,
+        Err(error) => std::panic::resume_unwind(error.into_panic()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `per_speaker` unit-ish vectors around each of `speakers` orthogonal
+    /// axes.
+    fn embeddings(speakers: usize, per_speaker: usize) -> Vec<Vec<f32>> {
+        (0..speakers * per_speaker)
+            .map(|index| {
+                let mut embedding = vec![0.05 * (index % 3) as f32; 8];
+                embedding[index % speakers] = 1.0;
+                embedding
+            })
+            .collect()
+    }
+
+    #[test]
+    fn jacobi_finds_known_eigenvalues() {
+        let eigenvalues = symmetric_eigenvalues(vec![
+            vec![2.0, 1.0, 0.0],
+            vec![1.0, 2.0, 0.0],
+            vec![0.0, 0.0, 5.0],
+        ]);
+        for (actual, expected) in eigenvalues.iter().zip([1.0, 3.0, 5.0]) {
+            assert!((actual - expected).abs() < 1e-9, "{eigenvalues:?}");
+        }
+    }
+
+    #[test]
+    fn counts_well_separated_speakers() {
+        for speakers in 2..=4 {
+            let estimate = eigengap(&embeddings(speakers, 6), 8, &Scorer::cosine());
+            assert_eq!(estimate.speaker_count, speakers);
+            assert!(estimate.confidence > 0.5, "{}", estimate.confidence);
+            let total: f64 = estimate
+                .candidates
+                .iter()
+                .map(|candidate| candidate.score)
+                .sum();
+            assert!((total - 1.0).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn candidates_stop_at_max_speakers() {
+        let estimate = eigengap(&embeddings(4, 3), 2, &Scorer::cosine());
+        assert_eq!(estimate.candidates.len(), 2);
+        assert!(estimate.speaker_count <= 2);
+    }
+
+    #[test]
+    fn too_few_segments_are_not_estimated() {
+        let estimate = eigengap(&embeddings(1, 1), 8, &Scorer::cosine());
+        assert_eq!(estimate.speaker_count, 1);
+        assert_eq!(estimate.confidence, 0.0);
+        assert!(estimate.candidates.is_empty());
+    }
+}
@@ -0,0 +1,88 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::State;
+use axum::response::Response;
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::clock::current_epoch_ms;
+use crate::state::ServerState;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerEvent {
+    SessionInactive {
+        session_id: String,
+        idle_ms: i64,
+        evicts_in_ms: i64,
+    },
+    /// The host was most likely suspended for about `jump_ms`; clients should
+    /// re-sync their window offsets.
+    ClockJump { jump_ms: i64 },
+}
+
+/// Streams server events as JSON text frames. Slow subscribers that fall more
+/// than the channel capacity behind skip the missed events rather than stall
+/// the broadcaster.
+pub async fn subscribe(
+    State(state): State<Arc<ServerState>>,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    let receiver = state.events.subscribe();
+    upgrade.on_upgrade(move |socket| forward_events(socket, receiver))
+}
+
+async fn forward_events(mut socket: WebSocket, mut receiver: broadcast::Receiver<ServerEvent>) {
+    loop {
+        tokio::select! {
+            event = receiver.recv() => {
+                let event = match event {
+                    Ok(event) => event,
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                let Ok(payload) = serde_json::to_string(&event) else {
+                    continue;
+                };
+                if socket.send(Message::Text(payload.into())).await.is_err() {
+                    break;
+                }
+            }
+            incoming = socket.recv() => {
+                match incoming {
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => {}
+                }
+            }
+        }
+    }
+}
+
+/// Warns once per quiet period when a session has received no audio for
+/// `idle_warning_ms`, ahead of TTL eviction. The watch is armed by the first
+/// streamed window and rearmed by every later one, so a session can warn
+/// several times over its lifetime but never before it was streamed to.
+pub async fn watch_inactivity(state: Arc<ServerState>, idle_warning_ms: i64) {
+    let poll_interval = Duration::from_millis((idle_warning_ms / 4).clamp(250, 5_000) as u64);
+    let mut ticker = tokio::time::interval(poll_interval);
+    loop {
+        ticker.tick().await;
+        crate::clock::check(&state);
+        let now_ms = current_epoch_ms();
+        for (session_id, session) in state.sessions.all() {
+            let activity = session.activity();
+            let idle_ms = now_ms - activity.last_seen_ms();
+            if idle_ms < idle_warning_ms || !activity.mark_inactivity_notified() {
+                continue;
+            }
+            // Sending only fails when nobody is subscribed, which is fine.
+            let _ = state.events.send(ServerEvent::SessionInactive {
+                session_id,
+                idle_ms,
+                evicts_in_ms: (state.config.session_ttl_ms - idle_ms).max(0),
+            });
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use std::f64::consts::PI;
+
+use serde::Serialize;
+
+/// Frame length for the effective duration measure.
+const FRAME_MS: usize = 10;
+
+/// Frame RMS below which the frame counts as silence, about -50 dBFS.
+const SILENCE_RMS: f64 = 100.0;
+
+/// Furthest a gated segment may be from the speech it takes its speaker from.
+pub const CONTINUITY_MAX_GAP_MS: i64 = 2000;
+
+/// Decides which segments are worth an embedding. Very short or narrowband
+/// segments (a cough, a "mm", hum bleeding through) give unreliable
+/// embeddings that tend to enroll spurious speakers; gated segments are
+/// instead given the speaker of the nearest speech around them. The default
+/// gate lets everything through.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct EmbeddingGate {
+    /// Minimum duration of non-silent 10 ms frames.
+    pub min_effective_ms: i64,
+    /// Minimum RMS frequency, a cheap bandwidth estimate from the energy of
+    /// the signal's first difference.
+    pub min_rms_hz: f64,
+}
+
+impl EmbeddingGate {
+    pub fn admits(&self, samples: &[i16], sample_rate: u32) -> bool {
+        if self.min_effective_ms > 0 && effective_ms(samples, sample_rate) < self.min_effective_ms {
+            return false;
+        }
+        if self.min_rms_hz > 0.0 && rms_frequency_hz(samples, sample_rate) < self.min_rms_hz {
+            return false;
+        }
+        true
+    }
+}
+
+fn effective_ms(samples: &[i16], sample_rate: u32) -> i64 {
+    let frame_len = (sample_rate as usize * FRAME_MS / 1000).max(1);
+    let voiced_frames = samples
+        .chunks(frame_len)
+        .filter(|frame| {
+            let energy: f64 = frame.iter().map(|&sample| (sample as f64).powi(2)).sum();
+            (energy / frame.len() as f64).sqrt() >= SILENCE_RMS
+        })
+        .count();
+    (voiced_frames * FRAME_MS) as i64
+}
+
+/// For a sine of frequency `f` the first difference carries
+/// `4 sin²(πf/fs)` times the signal energy, which inverts to `f`; for other
+/// signals it gives an energy-weighted average frequency.
+fn rms_frequency_hz(samples: &[i16], sample_rate: u32) -> f64 {
+    let energy: f64 = samples.iter().map(|&sample| (sample as f64).powi(2)).sum();
+    if energy == 0.0 {
+        return 0.0;
+    }
+    let difference_energy: f64 = samples
+        .windows(2)
+        .map(|pair| (pair[1] as f64 - pair[0] as f64).powi(2))
+        .sum();
+    let ratio = (difference_energy / (4.0 * energy)).sqrt().min(1.0);
+    ratio.asin() / PI * sample_rate as f64
+}
+
+/// Speaker of the span in `assigned` closest in time to `span`, if one lies
+/// within [`CONTINUITY_MAX_GAP_MS`]. Ties go to the earlier span.
+pub fn nearest_speaker(span: (i64, i64), assigned: &[(i64, i64, usize)]) -> Option<usize> {
+    assigned
+        .iter()
+        .map(|&(start_ms, end_ms, speaker_id)| {
+            let gap_ms = (start_ms - span.1).max(span.0 - end_ms).max(0);
+            (gap_ms, start_ms, speaker_id)
+        })
+        .filter(|(gap_ms, _, _)| *gap_ms <= CONTINUITY_MAX_GAP_MS)
+        .min()
+        .map(|(_, _, speaker_id)| speaker_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 16_000;
+
+    fn sine(frequency_hz: f64, amplitude: f64, duration_ms: usize) -> Vec<i16> {
+        (0..SAMPLE_RATE as usize * duration_ms / 1000)
+            .map(|index| {
+                let phase = 2.0 * PI * frequency_hz * index as f64 / SAMPLE_RATE as f64;
+                (amplitude * phase.sin()).round() as i16
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rms_frequency_recovers_a_sine() {
+        for frequency_hz in [200.0, 1000.0, 3000.0] {
+            let estimate = rms_frequency_hz(&sine(frequency_hz, 8000.0, 500), SAMPLE_RATE);
+            assert!(
+                (estimate - frequency_hz).abs() < frequency_hz * 0.01,
+                "{frequency_hz} Hz estimated as {estimate}"
+            );
+        }
+        assert_eq!(rms_frequency_hz(&[0; 160], SAMPLE_RATE), 0.0);
+    }
+
+    #[test]
+    fn near_silent_frames_do_not_count_as_effective() {
+        let mut samples = sine(440.0, 8000.0, 300);
+        samples.extend(sine(440.0, 50.0, 700));
+        assert_eq!(effective_ms(&samples, SAMPLE_RATE), 300);
+    }
+
+    #[test]
+    fn gate_checks_duration_and_bandwidth() {
+        let tone = sine(150.0, 8000.0, 400);
+        assert!(EmbeddingGate::default().admits(&tone, SAMPLE_RATE));
+
+        let duration_gate = EmbeddingGate {
+            min_effective_ms: 500,
+            min_rms_hz: 0.0,
+        };
+        assert!(!duration_gate.admits(&tone, SAMPLE_RATE));
+        assert!(duration_gate.admits(&sine(150.0, 8000.0, 600), SAMPLE_RATE));
+
+        let bandwidth_gate = EmbeddingGate {
+            min_effective_ms: 0,
+            min_rms_hz: 300.0,
+        };
+        assert!(!bandwidth_gate.admits(&tone, SAMPLE_RATE));
+        assert!(bandwidth_gate.admits(&sine(1000.0, 8000.0, 400), SAMPLE_RATE));
+    }
+
+    #[test]
+    fn nearest_speaker_respects_the_gap_limit() {
+        let assigned = [(0, 1000, 1)];
+        assert_eq!(
+            nearest_speaker((1000 + CONTINUITY_MAX_GAP_MS, 4000), &assigned),
+            Some(1)
+        );
+        assert_eq!(
+            nearest_speaker((1001 + CONTINUITY_MAX_GAP_MS, 4000), &assigned),
+            None
+        );
+        assert_eq!(nearest_speaker((500, 700), &assigned), Some(1));
+        assert_eq!(nearest_speaker((500, 700), &[]), None);
+    }
+
+    #[test]
+    fn nearest_speaker_prefers_closer_then_earlier_spans() {
+        let assigned = [(3000, 4000, 2), (0, 1000, 1)];
+        assert_eq!(nearest_speaker((1800, 2800), &assigned), Some(2));
+        // 500 ms from both neighbours.
+        assert_eq!(nearest_speaker((1500, 2500), &assigned), Some(1));
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+
+use super::{compute_embedding, decode_pcm_s16le};
+use crate::error::AppError;
+use crate::state::ServerState;
+use crate::validation::ValidatedJson;
+use crate::{calibration, clustering, estimate, scheduler};
+
+#[derive(Debug, Deserialize)]
+pub struct CalibrationSnippet {
+    pub content_b64: String,
+    pub label: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CalibrateRequest {
+    pub snippets: Vec<CalibrationSnippet>,
+    pub threshold_from: Option<f32>,
+    pub threshold_to: Option<f32>,
+    pub threshold_step: Option<f32>,
+    pub max_speakers: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CalibrateResponse {
+    snippet_count: usize,
+    label_count: usize,
+    best: Option<calibration::CurvePoint>,
+    curve: Vec<calibration::CurvePoint>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EstimateSpeakersRequest {
+    pub content_b64: String,
+    pub sample_rate: Option<u32>,
+    pub max_speakers: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EstimateSpeakersResponse {
+    speaker_count: usize,
+    confidence: f64,
+    method: &'static str,
+    /// Count the session's agglomerative clustering arrives at with the
+    /// configured threshold, for comparison.
+    clustered_speaker_count: usize,
+    segment_count: usize,
+    candidates: Vec<estimate::Candidate>,
+    warnings: Vec<String>,
+}
+
+/// Segments embedded by `/estimate_speakers`; longer samples are subsampled
+/// evenly so the eigen decomposition stays fast.
+const MAX_ESTIMATE_SEGMENTS: usize = 200;
+
+/// Sweeps matching thresholds over labeled single-speaker snippets (16 kHz
+/// pcm_s16le). Snippets are scored in the order given, so they should follow
+/// the order speakers would appear in a real session.
+pub async fn calibrate(
+    State(state): State<Arc<ServerState>>,
+    ValidatedJson(req): ValidatedJson<CalibrateRequest>,
+) -> Result<Json<CalibrateResponse>, AppError> {
+    if req.snippets.len() < 2 {
+        return Err(AppError::bad_request(
+            "calibration needs at least two snippets",
+        ));
+    }
+
+    let from = req.threshold_from.unwrap_or(0.3);
+    let to = req.threshold_to.unwrap_or(0.8);
+    let step = req.threshold_step.unwrap_or(0.01);
+    if !(0.0..=1.0).contains(&from) || !(0.0..=1.0).contains(&to) || from > to {
+        return Err(AppError::bad_request(
+            "threshold_from and threshold_to must lie in [0,1] with from <= to",
+        ));
+    }
+    if !(0.001..=1.0).contains(&step) {
+        return Err(AppError::bad_request(
+            "threshold_step must lie in [0.001,1]",
+        ));
+    }
+
+    let models = state.models.read().await.clone();
+    let mut label_ids: HashMap<String, usize> = HashMap::new();
+    let mut labels = Vec::with_capacity(req.snippets.len());
+    let mut embeddings = Vec::with_capacity(req.snippets.len());
+    for (index, snippet) in req.snippets.iter().enumerate() {
+        let label = snippet.label.trim();
+        if label.is_empty() {
+            return Err(AppError::bad_request(format!(
+                "snippets[{index}].label is required"
+            )));
+        }
+        let next_id = label_ids.len() + 1;
+        labels.push(*label_ids.entry(label.to_string()).or_insert(next_id));
+
+        let samples = decode_pcm_s16le(&snippet.content_b64).map_err(|error| {
+            AppError::bad_request(format!("snippets[{index}]: {}", error.message))
+        })?;
+        embeddings.push(compute_embedding(&state.scorer, &models, &samples).await?);
+    }
+
+    let max_speakers = req.max_speakers.unwrap_or(state.config.max_speakers).max(1);
+    let curve = calibration::sweep(
+        &embeddings,
+        &labels,
+        (from, to, step),
+        max_speakers,
+        &state.scorer,
+    );
+    let best = calibration::best_point(&curve).cloned();
+
+    Ok(Json(CalibrateResponse {
+        snippet_count: embeddings.len(),
+        label_count: label_ids.len(),
+        best,
+        curve,
+    }))
+}
+
+/// Suggests how many people speak in an audio sample before a session is
+/// configured. Nothing is stored.
+pub async fn estimate_speakers(
+    State(state): State<Arc<ServerState>>,
+    ValidatedJson(req): ValidatedJson<EstimateSpeakersRequest>,
+) -> Result<Json<EstimateSpeakersResponse>, AppError> {
+    let sample_rate = req.sample_rate.unwrap_or(16_000);
+    if sample_rate == 0 {
+        return Err(AppError::bad_request("sample_rate must be positive"));
+    }
+    let max_speakers = req.max_speakers.unwrap_or(state.config.max_speakers).max(1);
+
+    let samples = decode_pcm_s16le(&req.content_b64)?;
+    // Estimation is as heavy as a short offline run and queues with them.
+    let _slot = state.scheduler.acquire(scheduler::Priority::Offline).await;
+    let models = state.models.read().await.clone();
+
+    let segmented = tokio::task::spawn_blocking({
+        let models = models.clone();
+        move || {
+            let mut warnings = Vec::new();
+            let mut segments = Vec::new();
+            let segments_iter =
+                pyannote_rs::get_segments(&samples, sample_rate, &models.segmentation_model)
+                    .map_err(|error| AppError::internal(format!("segmentation failed: {error}")))?;
+            for segment_result in segments_iter {
+                match segment_result {
+                    Ok(segment) if !segment.samples.is_empty() => segments.push(segment),
+                    Ok(_) => {}
+                    Err(error) => warnings.push(format!("segment skipped: {error}")),
+                }
+            }
+            Ok::<_, AppError>((segments, warnings))
+        }
+    })
+    .await;
+    let (segments, mut warnings) = match segmented {
+        Ok(segmented) => segmented?,
+        Err(error) => std::panic::resume_unwind(error.into_panic()),
+    };
+
+    let segment_count = segments.len();
+    let stride = segment_count.div_ceil(MAX_ESTIMATE_SEGMENTS).max(1);
+    if stride > 1 {
+        warnings.push(format!(
+            "{segment_count} segments found: every {stride}th used for estimation"
+        ));
+    }
+
+    let mut embeddings = Vec::new();
+    for segment in segments.iter().step_by(stride) {
+        embeddings.push(compute_embedding(&state.scorer, &models, &segment.samples).await?);
+    }
+    if embeddings.len() < 2 {
+        warnings.push("too few speech segments to estimate the speaker count".to_string());
+    }
+
+    let (embeddings, labels) = clustering::agglomerative_blocking(
+        embeddings,
+        state.config.threshold,
+        max_speakers,
+        state.scorer.clone(),
+        None,
+    )
+    .await;
+    let estimate =
+        estimate::eigengap_blocking(embeddings, max_speakers, state.scorer.clone()).await;
+
+    Ok(Json(EstimateSpeakersResponse {
+        speaker_count: estimate.speaker_count,
+        confidence: estimate.confidence,
+        method: "eigengap",
+        clustered_speaker_count: labels
+            .ok()
+            .and_then(|labels| labels.into_iter().max())
+            .unwrap_or(0),
+        segment_count,
+        candidates: estimate.candidates,
+        warnings,
+    }))
+}
@@ -0,0 +1,536 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+
+use axum::extract::State;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::{compute_embedding, decode_pcm_s16le, resolve_session_id, validate_metadata};
+use crate::clock::current_epoch_ms;
+use crate::error::AppError;
+use crate::profile::{self, Profiler};
+use crate::speakers::{self, SpeakerRegistry};
+use crate::state::{AudioTail, CachedResponse, ServerState, SessionState, WindowFingerprint};
+use crate::timeline::StoredEmbedding;
+use crate::track::{
+    map_segment_to_track, merge_adjacent_tracks, parse_speaker_id, Track, UNATTRIBUTED_SPEAKER_ID,
+};
+use crate::validation::ValidatedJson;
+use crate::{accounting, gating, sessions, snapshot, spectrum, timebase};
+
+#[derive(Debug, Deserialize)]
+pub struct DiarizeRequest {
+    /// Omit to start a new session under a server-minted id.
+    pub session_id: Option<String>,
+    pub content_b64: String,
+    pub sample_rate: Option<u32>,
+    pub start_end_ms: Option<[i64; 2]>,
+    pub threshold: Option<f32>,
+    pub max_speakers: Option<usize>,
+    pub idempotency_key: Option<String>,
+    pub profile: Option<bool>,
+    pub time_unit: Option<timebase::TimeUnit>,
+    /// Opaque client object echoed back on every track of the response.
+    pub metadata: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiarizeResponse {
+    pub session_id: String,
+    pub time_unit: timebase::TimeUnit,
+    pub tracks: Vec<Track>,
+    pub degraded: bool,
+    pub audio: accounting::AudioAccounting,
+    /// Speakers this window created or added speech to, so live clients can
+    /// keep a roster without polling.
+    pub speakers: Vec<speakers::SpeakerDelta>,
+    pub warnings: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<profile::ProfileReport>,
+}
+
+/// Largest gap or overlap between a window and the previous window's end
+/// that still counts as contiguous for pre-roll.
+const PRE_ROLL_TOLERANCE_MS: i64 = 20;
+
+fn fingerprint_window(
+    samples: &[i16],
+    sample_rate: u32,
+    start_end_ms: Option<[i64; 2]>,
+) -> WindowFingerprint {
+    let mut hasher = Sha256::new();
+    hasher.update(sample_rate.to_le_bytes());
+    match start_end_ms {
+        Some([start_ms, end_ms]) => {
+            hasher.update([1]);
+            hasher.update(start_ms.to_le_bytes());
+            hasher.update(end_ms.to_le_bytes());
+        }
+        None => hasher.update([0]),
+    }
+    for sample in samples {
+        hasher.update(sample.to_le_bytes());
+    }
+    hasher.finalize().into()
+}
+
+/// A decoded streaming window on its way to its session's worker.
+pub struct Window {
+    pub session_id: String,
+    pub samples: Vec<i16>,
+    pub sample_rate: u32,
+    pub start_end_ms: Option<[i64; 2]>,
+    pub threshold: f32,
+    pub idempotency_key: Option<String>,
+    pub time_unit: timebase::TimeUnit,
+    pub metadata: Option<serde_json::Value>,
+    pub profiler: Profiler,
+    pub queued_at: Instant,
+}
+
+pub async fn diarize(
+    State(state): State<Arc<ServerState>>,
+    ValidatedJson(req): ValidatedJson<DiarizeRequest>,
+) -> Result<Json<DiarizeResponse>, AppError> {
+    let session_id = resolve_session_id(req.session_id.as_deref())?;
+
+    let sample_rate = req.sample_rate.unwrap_or(16_000);
+    if sample_rate == 0 {
+        return Err(AppError::bad_request("sample_rate must be positive"));
+    }
+
+    let threshold = req
+        .threshold
+        .unwrap_or(state.config.threshold)
+        .clamp(0.0, 1.0);
+
+    let idempotency_key = req
+        .idempotency_key
+        .as_deref()
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+        .map(str::to_string);
+
+    let metadata = validate_metadata(req.metadata)?;
+
+    let mut profiler = Profiler::new(state.config.profile || req.profile.unwrap_or(false));
+    let decode_started_at = Instant::now();
+    let samples = decode_pcm_s16le(&req.content_b64)?;
+    profiler.record("decode", decode_started_at);
+
+    // New sessions pin the model pair active when their first window arrives.
+    let models = state.models.read().await.clone();
+    let max_speakers = req.max_speakers.unwrap_or(state.config.max_speakers);
+    let session = state
+        .sessions
+        .get_or_spawn(&state, &session_id, || SessionState {
+            manager: SpeakerRegistry::new(max_speakers),
+            config: snapshot::ConfigSnapshot::new(
+                &state.config,
+                &models,
+                &state.scorer,
+                max_speakers,
+            ),
+            models,
+            idempotent_responses: HashMap::new(),
+            window_fingerprints: HashMap::new(),
+            timeline: Vec::new(),
+            embeddings: Vec::new(),
+            audio: accounting::AudioAccounting::default(),
+            tail: None,
+        });
+
+    // Any received window counts as activity, even one that turns out to be
+    // silence, so the inactivity watcher only fires when capture has stopped.
+    session.touch();
+
+    let window = Window {
+        session_id,
+        samples,
+        sample_rate,
+        start_end_ms: req.start_end_ms,
+        threshold,
+        idempotency_key,
+        time_unit: req.time_unit.unwrap_or_default(),
+        metadata,
+        profiler,
+        queued_at: Instant::now(),
+    };
+    let mut response = session.diarize(window).await?;
+    if let Some(jump_ms) = session.activity().take_clock_jump_ms() {
+        response.warnings.push(format!(
+            "clock_jump: host clock jumped {jump_ms}ms, likely sleep; re-sync window offsets"
+        ));
+    }
+    Ok(Json(response))
+}
+
+/// Diarizes one window on the session's worker, which owns `session` for the
+/// duration, so windows of a session never interleave.
+pub async fn diarize_window(
+    state: &ServerState,
+    session: &mut SessionState,
+    window: Window,
+    cancel: &sessions::CancelToken,
+) -> Result<DiarizeResponse, AppError> {
+    let Window {
+        session_id,
+        samples,
+        sample_rate,
+        start_end_ms,
+        threshold,
+        idempotency_key,
+        time_unit,
+        metadata,
+        mut profiler,
+        queued_at,
+    } = window;
+    profiler.record("queue", queued_at);
+
+    let fingerprint = fingerprint_window(&samples, sample_rate, start_end_ms);
+    if let Some(key) = idempotency_key.as_deref() {
+        let now_ms = current_epoch_ms();
+        let cached = session
+            .idempotent_responses
+            .get(key)
+            .filter(|cached| now_ms - cached.stored_at_ms <= state.config.idempotency_ttl_ms);
+        if let Some(cached) = cached {
+            // A reused key on a different window is a client bug; replaying
+            // the first result would silently drop this window's audio.
+            if cached.fingerprint != fingerprint {
+                return Err(AppError::conflict(format!(
+                    "idempotency_key {key} was already used for a different window"
+                )));
+            }
+            return Ok(cached.response.clone());
+        }
+    }
+
+    // Clients that time out and resend the exact same window without an
+    // idempotency key are caught here, before the audio reaches the models.
+    // Without start_end_ms two identical windows (e.g. silence) may both be
+    // genuine, so only placed windows are deduplicated.
+    if start_end_ms.is_some() {
+        let now_ms = current_epoch_ms();
+        let cached = session
+            .window_fingerprints
+            .get(&fingerprint)
+            .filter(|cached| now_ms - cached.stored_at_ms <= state.config.idempotency_ttl_ms);
+        if let Some(cached) = cached {
+            let mut response = cached.response.clone();
+            response
+                .warnings
+                .push("duplicate window: returning original result".to_string());
+            return Ok(response);
+        }
+    }
+
+    let window_duration_ms = accounting::audio_ms(samples.len(), sample_rate);
+    let models = session.models.clone();
+
+    let (window_start_ms, window_end_ms) = match start_end_ms {
+        Some([start, end]) if start >= 0 && end >= start => (start, end),
+        Some(_) => {
+            return Err(AppError::bad_request(
+                "start_end_ms must be [start,end] and end >= start",
+            ))
+        }
+        None => (0, window_duration_ms.max(0)),
+    };
+
+    session.config.record_threshold(threshold);
+    let first_new_id = session.manager.next_speaker_id();
+    let mut warnings = Vec::new();
+    warnings.extend(spectrum::sample_rate_warning(&samples, sample_rate));
+    let mut tracks = Vec::new();
+    let mut window_embeddings = Vec::new();
+    let budget = state.config.latency_budgets.get("diarize").copied();
+    let started_at = Instant::now();
+
+    // The previous window's tail goes in front when this window continues
+    // it. Segment times stay relative to this window, so pre-roll speech
+    // comes out at negative times and is trimmed below.
+    let pre_roll = session.tail.take().filter(|tail| {
+        tail.sample_rate == sample_rate
+            && (tail.end_ms - window_start_ms).abs() <= PRE_ROLL_TOLERANCE_MS
+    });
+    let pre_roll_s = pre_roll
+        .as_ref()
+        .map_or(0.0, |tail| tail.samples.len() as f64 / sample_rate as f64);
+    let mut context = pre_roll.map(|tail| tail.samples).unwrap_or_default();
+    context.extend_from_slice(&samples);
+
+    let segments_iter =
+        pyannote_rs::get_segments(&context, sample_rate, &models.segmentation_model)
+            .map_err(|error| AppError::internal(format!("segmentation failed: {error}")))?;
+
+    let mut segments = Vec::new();
+    let mut segment_errors = 0;
+    for segment_result in segments_iter {
+        if cancel.is_cancelled() {
+            return Err(sessions::window_cancelled());
+        }
+        match segment_result {
+            Ok(mut segment) if !segment.samples.is_empty() => {
+                segment.start -= pre_roll_s;
+                segment.end -= pre_roll_s;
+                // Entirely inside the pre-roll: already in the last response.
+                if segment.end > 0.0 {
+                    segments.push(segment);
+                }
+            }
+            Ok(_) => {}
+            Err(error) => {
+                segment_errors += 1;
+                warnings.push(format!("segment skipped: {error}"));
+            }
+        }
+    }
+    profiler.record("segmentation", started_at);
+
+    // Last point at which the window can be dropped without leaving speakers
+    // enrolled that no track or stored embedding refers to.
+    if cancel.is_cancelled() {
+        return Err(sessions::window_cancelled());
+    }
+
+    // Falling behind live audio is worse than losing speaker attribution, so
+    // past the soft budget (after segmentation) or the hard budget (at any
+    // point) the remaining segments are returned as unattributed speech.
+    let segmentation_elapsed = started_at.elapsed();
+    let mut degraded = false;
+    if let Some(budget) = budget.filter(|budget| segmentation_elapsed > budget.soft) {
+        degraded = true;
+        warnings.push(format!(
+            "segmentation took {}ms, over the {}ms soft budget: embeddings skipped",
+            segmentation_elapsed.as_millis(),
+            budget.soft.as_millis()
+        ));
+    }
+
+    if let Some(silence_ms) = state.config.retire_silent_speakers_ms {
+        for speaker_id in session.manager.retire_silent(window_start_ms, silence_ms) {
+            warnings.push(format!(
+                "edge_spk_{speaker_id} retired from matching after {}s without speech",
+                silence_ms / 1000
+            ));
+        }
+    }
+
+    let voiced: Vec<(i64, i64)> = segments
+        .iter()
+        .map(|segment| {
+            let track = map_segment_to_track(segment, window_start_ms, window_end_ms, 0);
+            (track.start_ms, track.end_ms)
+        })
+        .collect();
+    let mut unassigned = Vec::new();
+    let mut gated = Vec::new();
+
+    for segment in segments {
+        if let Some(budget) =
+            budget.filter(|budget| !degraded && started_at.elapsed() > budget.hard)
+        {
+            degraded = true;
+            warnings.push(format!(
+                "request exceeded the {}ms hard budget: remaining embeddings skipped",
+                budget.hard.as_millis()
+            ));
+        }
+
+        if degraded {
+            tracks.push(Track {
+                speaker_id: UNATTRIBUTED_SPEAKER_ID.to_string(),
+                ..map_segment_to_track(&segment, window_start_ms, window_end_ms, 0)
+            });
+            continue;
+        }
+
+        if !state
+            .config
+            .embedding_gate
+            .admits(&segment.samples, sample_rate)
+        {
+            gated.push(segment);
+            continue;
+        }
+
+        let embedding_started_at = Instant::now();
+        let embedding = compute_embedding(&state.scorer, &models, &segment.samples).await?;
+        profiler.record("embedding", embedding_started_at);
+
+        let clustering_started_at = Instant::now();
+        let speaker_id =
+            match session
+                .manager
+                .search_speaker(embedding.clone(), threshold, &state.scorer)
+            {
+                Some(id) => id,
+                None => session
+                    .manager
+                    .best_speaker_match(&embedding, &state.scorer)
+                    .unwrap_or(0),
+            };
+        profiler.record("clustering", clustering_started_at);
+
+        if speaker_id == 0 {
+            warnings.push("speaker assignment returned 0, segment dropped".to_string());
+            let track = map_segment_to_track(&segment, window_start_ms, window_end_ms, 0);
+            unassigned.push((track.start_ms, track.end_ms));
+            continue;
+        }
+
+        let track = map_segment_to_track(&segment, window_start_ms, window_end_ms, speaker_id);
+        session.manager.heard(speaker_id, track.end_ms);
+        window_embeddings.push(StoredEmbedding {
+            speaker_id,
+            start_ms: track.start_ms,
+            end_ms: track.end_ms,
+            embedding,
+        });
+        tracks.push(track);
+    }
+
+    // Gated segments follow the nearest embedded speech in this window, or
+    // the end of the session timeline when the window has none close by.
+    if !gated.is_empty() {
+        let mut assigned: Vec<(i64, i64, usize)> = window_embeddings
+            .iter()
+            .map(|stored| (stored.start_ms, stored.end_ms, stored.speaker_id))
+            .collect();
+        if let Some(last) = session.timeline.last() {
+            if let Some(speaker_id) = parse_speaker_id(&last.speaker_id) {
+                assigned.push((last.start_ms, last.end_ms, speaker_id));
+            }
+        }
+        let mut dropped = 0;
+        for segment in &gated {
+            let track = map_segment_to_track(segment, window_start_ms, window_end_ms, 0);
+            match gating::nearest_speaker((track.start_ms, track.end_ms), &assigned) {
+                Some(speaker_id) => {
+                    session.manager.heard(speaker_id, track.end_ms);
+                    tracks.push(map_segment_to_track(
+                        segment,
+                        window_start_ms,
+                        window_end_ms,
+                        speaker_id,
+                    ));
+                }
+                None => {
+                    dropped += 1;
+                    unassigned.push((track.start_ms, track.end_ms));
+                }
+            }
+        }
+        if dropped > 0 {
+            warnings.push(format!(
+                "{dropped} segment(s) too short or narrowband to embed and without nearby speech, dropped"
+            ));
+        }
+    }
+
+    let merge_started_at = Instant::now();
+    let tracks = state
+        .config
+        .postprocess
+        .apply(tracks, window_start_ms, window_end_ms);
+    profiler.record("merge", merge_started_at);
+    // A declared span may be off by a few ms; the audio itself is what was
+    // received, as for windows that fail.
+    let audio = accounting::AudioAccounting::processed(
+        (window_start_ms, window_start_ms + window_duration_ms),
+        &voiced,
+        &unassigned,
+        &tracks,
+        segment_errors,
+    );
+
+    let mut tracks = tracks;
+    timebase::apply(&mut tracks, time_unit, sample_rate);
+    for track in &mut tracks {
+        track.metadata = metadata.clone();
+    }
+
+    let profile = profiler.finish(state.config.profile_trace_dir.as_deref(), &session_id);
+    let mut response = DiarizeResponse {
+        session_id,
+        time_unit,
+        tracks,
+        degraded,
+        audio,
+        speakers: Vec::new(),
+        warnings,
+        profile,
+    };
+
+    let now_ms = current_epoch_ms();
+    let ttl_ms = state.config.idempotency_ttl_ms;
+    let mut timeline = std::mem::take(&mut session.timeline);
+    timeline.extend(
+        response
+            .tracks
+            .iter()
+            .filter(|track| track.speaker_id != UNATTRIBUTED_SPEAKER_ID)
+            .map(|track| Track {
+                unit_times: None,
+                metadata: None,
+                ..track.clone()
+            }),
+    );
+    session.timeline = merge_adjacent_tracks(timeline, state.config.postprocess.merge_gap_ms());
+    session
+        .manager
+        .record(&window_embeddings, &response.tracks, &state.scorer);
+    session.embeddings.append(&mut window_embeddings);
+    session.audio += audio;
+    response.speakers = speakers::deltas(&session.manager, &response.tracks, first_new_id);
+    for delta in &mut response.speakers {
+        delta.speaker_id = state
+            .config
+            .postprocess
+            .label(&delta.speaker_id)
+            .to_string();
+    }
+    state.config.postprocess.relabel(&mut response.tracks);
+    if state.config.pre_roll_ms > 0 {
+        let keep = (state.config.pre_roll_ms * sample_rate as i64 / 1000) as usize;
+        context.drain(..context.len().saturating_sub(keep));
+        session.tail = Some(AudioTail {
+            samples: context,
+            sample_rate,
+            end_ms: window_end_ms,
+        });
+    }
+
+    session
+        .window_fingerprints
+        .retain(|_, cached| now_ms - cached.stored_at_ms <= ttl_ms);
+    if start_end_ms.is_some() {
+        session.window_fingerprints.insert(
+            fingerprint,
+            CachedResponse {
+                response: response.clone(),
+                fingerprint,
+                stored_at_ms: now_ms,
+            },
+        );
+    }
+
+    if let Some(key) = idempotency_key {
+        session
+            .idempotent_responses
+            .retain(|_, cached| now_ms - cached.stored_at_ms <= ttl_ms);
+        session.idempotent_responses.insert(
+            key,
+            CachedResponse {
+                response: response.clone(),
+                fingerprint,
+                stored_at_ms: now_ms,
+            },
+        );
+    }
+
+    Ok(response)
+}
@@ -0,0 +1,32 @@
+use std::sync::Arc;
+
+use axum::extract::{Path as UrlPath, State};
+use axum::http::StatusCode;
+use axum::Json;
+
+use crate::error::AppError;
+use crate::jobs;
+use crate::state::ServerState;
+
+pub async fn get_job(
+    State(state): State<Arc<ServerState>>,
+    UrlPath(job_id): UrlPath<String>,
+) -> Result<Json<jobs::JobView>, AppError> {
+    let job = state
+        .jobs
+        .get(&job_id)
+        .ok_or_else(|| AppError::not_found(format!("unknown job: {job_id}")))?;
+    Ok(Json(job.view()))
+}
+
+pub async fn cancel_job(
+    State(state): State<Arc<ServerState>>,
+    UrlPath(job_id): UrlPath<String>,
+) -> Result<(StatusCode, Json<jobs::JobView>), AppError> {
+    let job = state
+        .jobs
+        .get(&job_id)
+        .ok_or_else(|| AppError::not_found(format!("unknown job: {job_id}")))?;
+    job.cancel()?;
+    Ok((StatusCode::ACCEPTED, Json(job.view())))
+}
@@ -0,0 +1,104 @@
+pub mod calibrate;
+pub mod diarize;
+pub mod jobs;
+pub mod offline;
+pub mod sessions;
+pub mod status;
+
+use base64::engine::general_purpose::STANDARD as BASE64_STANDARD;
+use base64::Engine;
+
+use crate::error::AppError;
+use crate::models::ModelSet;
+use crate::scoring::Scorer;
+
+pub use calibrate::{calibrate, estimate_speakers};
+pub use diarize::diarize;
+pub use jobs::{cancel_job, get_job};
+pub use offline::{diarize_offline, submit_offline_job};
+pub use sessions::{
+    cancel_session, delete_timeline_range, erase_speaker_embeddings, export_session,
+    recluster_session, session_analytics, session_audio, session_summary, session_talk_ratio,
+};
+pub use status::{health, list_models, ready, reload_models};
+
+/// Upper bound on the serialized size of a request's `metadata` object.
+const MAX_METADATA_BYTES: usize = 4096;
+
+fn decode_pcm_s16le(content_b64: &str) -> Result<Vec<i16>, AppError> {
+    let bytes = BASE64_STANDARD
+        .decode(content_b64.as_bytes())
+        .map_err(|error| AppError::bad_request(format!("invalid base64 pcm payload: {error}")))?;
+
+    if bytes.is_empty() {
+        return Err(AppError::bad_request(
+            "content_b64 decoded to empty payload",
+        ));
+    }
+    if bytes.len() % 2 != 0 {
+        return Err(AppError::bad_request(
+            "pcm payload must contain even number of bytes",
+        ));
+    }
+
+    let mut samples = Vec::with_capacity(bytes.len() / 2);
+    for chunk in bytes.chunks_exact(2) {
+        samples.push(i16::from_le_bytes([chunk[0], chunk[1]]));
+    }
+    Ok(samples)
+}
+
+/// Uses the client's session id, or mints a random UUID when it is omitted.
+/// Client ids persisted across app restarts have collided before, silently
+/// mixing two interviews' speakers. A blank id is rejected as a likely client
+/// bug rather than treated as omitted.
+fn resolve_session_id(raw: Option<&str>) -> Result<String, AppError> {
+    match raw.map(str::trim) {
+        Some("") => Err(AppError::bad_request(
+            "session_id must not be blank; omit it to have one generated",
+        )),
+        Some(session_id) => Ok(session_id.to_string()),
+        None => Ok(uuid::Uuid::new_v4().to_string()),
+    }
+}
+
+fn validate_metadata(
+    metadata: Option<serde_json::Value>,
+) -> Result<Option<serde_json::Value>, AppError> {
+    let Some(metadata) = metadata.filter(|value| !value.is_null()) else {
+        return Ok(None);
+    };
+    if !metadata.is_object() {
+        return Err(AppError::bad_request("metadata must be a JSON object"));
+    }
+    if metadata.to_string().len() > MAX_METADATA_BYTES {
+        return Err(AppError::bad_request(format!(
+            "metadata must serialize to at most {MAX_METADATA_BYTES} bytes"
+        )));
+    }
+    Ok(Some(metadata))
+}
+
+async fn compute_embedding(
+    scorer: &Scorer,
+    models: &ModelSet,
+    samples: &[i16],
+) -> Result<Vec<f32>, AppError> {
+    let embedding: Vec<f32> = {
+        let mut extractor = models.extractor.lock().await;
+        extractor
+            .compute(samples)
+            .map_err(|error| AppError::internal(format!("embedding failed: {error}")))?
+            .collect()
+    };
+
+    if let Some(dim) = scorer.expected_dim() {
+        if embedding.len() != dim {
+            return Err(AppError::internal(format!(
+                "embedding dimension {} does not match scoring backend dimension {dim}",
+                embedding.len()
+            )));
+        }
+    }
+    Ok(embedding)
+}
@@ -0,0 +1,443 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+
+use super::{compute_embedding, decode_pcm_s16le, resolve_session_id, validate_metadata};
+use crate::cli::LatencyBudget;
+use crate::error::AppError;
+use crate::models::ModelSet;
+use crate::profile::{self, Profiler};
+use crate::scoring::Scorer;
+use crate::speakers::SpeakerRegistry;
+use crate::state::{ServerState, SessionState};
+use crate::timeline::StoredEmbedding;
+use crate::track::{map_segment_to_track, Track};
+use crate::validation::ValidatedJson;
+use crate::{
+    accounting, analytics, clustering, gating, jobs, postprocess, scheduler, sessions, snapshot,
+    spectrum, timebase,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct OfflineDiarizeRequest {
+    /// Omit to have the server mint one.
+    pub session_id: Option<String>,
+    pub content_b64: String,
+    pub sample_rate: Option<u32>,
+    pub threshold: Option<f32>,
+    pub max_speakers: Option<usize>,
+    pub profile: Option<bool>,
+    pub time_unit: Option<timebase::TimeUnit>,
+    /// Opaque client object echoed back on every track of the response.
+    pub metadata: Option<serde_json::Value>,
+    pub priority: Option<scheduler::Priority>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OfflineDiarizeResponse {
+    pub session_id: String,
+    pub speaker_count: usize,
+    pub time_unit: timebase::TimeUnit,
+    pub tracks: Vec<Track>,
+    pub analytics: analytics::AnalyticsResponse,
+    pub audio: accounting::AudioAccounting,
+    pub warnings: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<profile::ProfileReport>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct OfflineOptions {
+    pub sample_rate: u32,
+    pub threshold: f32,
+    pub max_speakers: usize,
+    pub budget: Option<LatencyBudget>,
+    pub gate: gating::EmbeddingGate,
+}
+
+/// A whole recording diarized in one pass, ready to become a session or a
+/// report.
+pub struct Recording {
+    pub speaker_count: usize,
+    pub tracks: Vec<Track>,
+    pub manager: SpeakerRegistry,
+    pub embeddings: Vec<StoredEmbedding>,
+    pub audio: accounting::AudioAccounting,
+    pub warnings: Vec<String>,
+}
+
+/// Embeds every segment of a recording first and clusters them globally, so
+/// early speaker assignments never drift the way the greedy streaming path
+/// can. Shared by `/diarize/offline` and the `offline` command.
+pub async fn diarize_recording(
+    scorer: &Scorer,
+    models: &ModelSet,
+    postprocess: &postprocess::Pipeline,
+    options: OfflineOptions,
+    samples: &[i16],
+    profiler: &mut Profiler,
+    progress: &jobs::Progress,
+) -> Result<Recording, AppError> {
+    let OfflineOptions {
+        sample_rate,
+        threshold,
+        max_speakers,
+        budget,
+        gate,
+    } = options;
+    let recording_end_ms = accounting::audio_ms(samples.len(), sample_rate);
+    let recording_end_s = samples.len() as f64 / sample_rate as f64;
+
+    let mut warnings = Vec::new();
+    warnings.extend(spectrum::sample_rate_warning(samples, sample_rate));
+    let mut segments = Vec::new();
+    let mut gated = Vec::new();
+    let mut segment_errors = 0;
+    let mut embeddings = Vec::new();
+    let started_at = Instant::now();
+
+    let mut segments_iter =
+        pyannote_rs::get_segments(samples, sample_rate, &models.segmentation_model)
+            .map_err(|error| AppError::internal(format!("segmentation failed: {error}")))?;
+
+    // Segmentation runs lazily inside the iterator, so each `next` call is
+    // timed separately from the embedding that follows it.
+    loop {
+        let segmentation_started_at = Instant::now();
+        let Some(segment_result) = segments_iter.next() else {
+            profiler.record("segmentation", segmentation_started_at);
+            break;
+        };
+        profiler.record("segmentation", segmentation_started_at);
+
+        let segment = match segment_result {
+            Ok(segment) => segment,
+            Err(error) => {
+                segment_errors += 1;
+                warnings.push(format!("segment skipped: {error}"));
+                continue;
+            }
+        };
+
+        if segment.samples.is_empty() {
+            continue;
+        }
+
+        if let Some(budget) = budget.filter(|budget| started_at.elapsed() > budget.hard) {
+            return Err(AppError::unavailable(format!(
+                "offline diarization exceeded the {}ms hard budget",
+                budget.hard.as_millis()
+            )));
+        }
+        progress.yield_to_live().await;
+        if progress.is_cancelled() {
+            return Err(AppError::conflict("offline diarization cancelled"));
+        }
+
+        progress.set(segment.end / recording_end_s);
+        if !gate.admits(&segment.samples, sample_rate) {
+            gated.push(segment);
+            continue;
+        }
+
+        let embedding_started_at = Instant::now();
+        let embedding = compute_embedding(scorer, models, &segment.samples).await?;
+        profiler.record("embedding", embedding_started_at);
+
+        segments.push(segment);
+        embeddings.push(embedding);
+    }
+
+    if let Some(budget) = budget.filter(|budget| started_at.elapsed() > budget.soft) {
+        warnings.push(format!(
+            "offline diarization took {}ms, over the {}ms soft budget",
+            started_at.elapsed().as_millis(),
+            budget.soft.as_millis()
+        ));
+    }
+
+    let clustering_started_at = Instant::now();
+    let (embeddings, labels) = clustering::agglomerative_blocking(
+        embeddings,
+        threshold,
+        max_speakers,
+        scorer.clone(),
+        budget.map(|budget| started_at + budget.hard),
+    )
+    .await;
+    let labels = labels.map_err(|_| {
+        AppError::unavailable(format!(
+            "offline diarization exceeded the {}ms hard budget",
+            budget.map_or(0, |budget| budget.hard.as_millis())
+        ))
+    })?;
+    let speaker_count = labels.iter().copied().max().unwrap_or(0);
+    profiler.record("clustering", clustering_started_at);
+
+    let mut manager = SpeakerRegistry::new(max_speakers);
+    for label in 1..=speaker_count {
+        let cluster: Vec<&[f32]> = labels
+            .iter()
+            .zip(&embeddings)
+            .filter(|(item, _)| **item == label)
+            .map(|(_, embedding)| embedding.as_slice())
+            .collect();
+        manager.add_speaker(clustering::centroid(&cluster));
+    }
+
+    let mut stored_embeddings = Vec::with_capacity(segments.len());
+    let mut tracks = Vec::with_capacity(segments.len());
+    for ((segment, &label), embedding) in segments.iter().zip(&labels).zip(embeddings) {
+        let track = map_segment_to_track(segment, 0, recording_end_ms, label);
+        stored_embeddings.push(StoredEmbedding {
+            speaker_id: label,
+            start_ms: track.start_ms,
+            end_ms: track.end_ms,
+            embedding,
+        });
+        tracks.push(track);
+    }
+    let mut voiced: Vec<(i64, i64)> = tracks
+        .iter()
+        .map(|track| (track.start_ms, track.end_ms))
+        .collect();
+
+    // Gated segments take the label of the nearest clustered segment.
+    let assigned: Vec<(i64, i64, usize)> = stored_embeddings
+        .iter()
+        .map(|stored| (stored.start_ms, stored.end_ms, stored.speaker_id))
+        .collect();
+    let mut unassigned = Vec::new();
+    for segment in &gated {
+        let track = map_segment_to_track(segment, 0, recording_end_ms, 0);
+        voiced.push((track.start_ms, track.end_ms));
+        match gating::nearest_speaker((track.start_ms, track.end_ms), &assigned) {
+            Some(label) => tracks.push(map_segment_to_track(segment, 0, recording_end_ms, label)),
+            None => unassigned.push((track.start_ms, track.end_ms)),
+        }
+    }
+    if !unassigned.is_empty() {
+        warnings.push(format!(
+            "{} segment(s) too short or narrowband to embed and without nearby speech, dropped",
+            unassigned.len()
+        ));
+    }
+
+    let merge_started_at = Instant::now();
+    let tracks = postprocess.apply(tracks, 0, recording_end_ms);
+    profiler.record("merge", merge_started_at);
+    manager.recount(&stored_embeddings, &tracks, scorer);
+    let audio = accounting::AudioAccounting::processed(
+        (0, recording_end_ms),
+        &voiced,
+        &unassigned,
+        &tracks,
+        segment_errors,
+    );
+
+    Ok(Recording {
+        speaker_count,
+        tracks,
+        manager,
+        embeddings: stored_embeddings,
+        audio,
+        warnings,
+    })
+}
+
+/// A validated and decoded `/diarize/offline` request.
+pub struct OfflineInput {
+    session: sessions::Reservation,
+    samples: Vec<i16>,
+    options: OfflineOptions,
+    metadata: Option<serde_json::Value>,
+    time_unit: timebase::TimeUnit,
+    priority: scheduler::Priority,
+    profiler: Profiler,
+}
+
+fn prepare_offline(
+    state: &Arc<ServerState>,
+    req: OfflineDiarizeRequest,
+) -> Result<OfflineInput, AppError> {
+    let session_id = resolve_session_id(req.session_id.as_deref())?;
+
+    let sample_rate = req.sample_rate.unwrap_or(16_000);
+    if sample_rate == 0 {
+        return Err(AppError::bad_request("sample_rate must be positive"));
+    }
+
+    let threshold = req
+        .threshold
+        .unwrap_or(state.config.threshold)
+        .clamp(0.0, 1.0);
+    let max_speakers = req.max_speakers.unwrap_or(state.config.max_speakers).max(1);
+    let metadata = validate_metadata(req.metadata)?;
+
+    let mut profiler = Profiler::new(state.config.profile || req.profile.unwrap_or(false));
+    let decode_started_at = Instant::now();
+    let samples = decode_pcm_s16le(&req.content_b64)?;
+    profiler.record("decode", decode_started_at);
+
+    Ok(OfflineInput {
+        session: state.sessions.reserve(state, &session_id)?,
+        samples,
+        options: OfflineOptions {
+            sample_rate,
+            threshold,
+            max_speakers,
+            budget: state.config.latency_budgets.get("diarize_offline").copied(),
+            gate: state.config.embedding_gate,
+        },
+        metadata,
+        time_unit: req.time_unit.unwrap_or_default(),
+        priority: req.priority.unwrap_or_default(),
+        profiler,
+    })
+}
+
+/// A diarized recording whose session is not created yet.
+pub struct OfflineRun {
+    reservation: sessions::Reservation,
+    session: SessionState,
+    response: OfflineDiarizeResponse,
+}
+
+impl OfflineRun {
+    /// Creates the session and returns the response describing it.
+    fn commit(self, state: &Arc<ServerState>) -> Result<OfflineDiarizeResponse, AppError> {
+        state
+            .sessions
+            .insert(state, self.reservation, self.session)?;
+        Ok(self.response)
+    }
+}
+
+/// Diarizes a complete recording in one pass (see `diarize_recording`).
+/// Committing the run creates a regular session whose speakers are seeded
+/// from the cluster centroids.
+async fn run_offline(
+    state: &Arc<ServerState>,
+    input: OfflineInput,
+    progress: &jobs::Progress,
+) -> Result<OfflineRun, AppError> {
+    let OfflineInput {
+        session: reservation,
+        samples,
+        options,
+        metadata,
+        time_unit,
+        priority: _,
+        mut profiler,
+    } = input;
+    let session_id = reservation.session_id().to_string();
+    let models = state.models.read().await.clone();
+
+    let Recording {
+        speaker_count,
+        tracks,
+        manager,
+        embeddings: stored_embeddings,
+        audio,
+        warnings,
+    } = diarize_recording(
+        &state.scorer,
+        &models,
+        &state.config.postprocess,
+        options,
+        &samples,
+        &mut profiler,
+        progress,
+    )
+    .await?;
+
+    let mut labeled = tracks.clone();
+    state.config.postprocess.relabel(&mut labeled);
+    let analytics = analytics::analyze(&session_id, &labeled, None);
+    let mut config =
+        snapshot::ConfigSnapshot::new(&state.config, &models, &state.scorer, options.max_speakers);
+    config.record_threshold(options.threshold);
+
+    let session = SessionState {
+        manager,
+        models,
+        idempotent_responses: HashMap::new(),
+        window_fingerprints: HashMap::new(),
+        timeline: tracks,
+        embeddings: stored_embeddings,
+        audio,
+        tail: None,
+        config,
+    };
+
+    let mut tracks = labeled;
+    timebase::apply(&mut tracks, time_unit, options.sample_rate);
+    for track in &mut tracks {
+        track.metadata = metadata.clone();
+    }
+
+    let profile = profiler.finish(state.config.profile_trace_dir.as_deref(), &session_id);
+    Ok(OfflineRun {
+        reservation,
+        session,
+        response: OfflineDiarizeResponse {
+            session_id,
+            speaker_count,
+            time_unit,
+            tracks,
+            analytics,
+            audio,
+            warnings,
+            profile,
+        },
+    })
+}
+
+pub async fn diarize_offline(
+    State(state): State<Arc<ServerState>>,
+    ValidatedJson(req): ValidatedJson<OfflineDiarizeRequest>,
+) -> Result<Json<OfflineDiarizeResponse>, AppError> {
+    let input = prepare_offline(&state, req)?;
+    let _slot = state.scheduler.acquire(input.priority).await;
+    let progress = jobs::Progress::yielding_to(state.scheduler.clone());
+    run_offline(&state, input, &progress)
+        .await?
+        .commit(&state)
+        .map(Json)
+}
+
+/// Runs the `/diarize/offline` work in the background and answers at once
+/// with a job to poll, so long recordings do not hold a request open past
+/// proxy and client timeouts. The request is validated and decoded, and its
+/// session id claimed, up front; the job stays queued until an offline worker
+/// slot for its priority frees.
+pub async fn submit_offline_job(
+    State(state): State<Arc<ServerState>>,
+    ValidatedJson(req): ValidatedJson<OfflineDiarizeRequest>,
+) -> Result<(StatusCode, Json<jobs::JobView>), AppError> {
+    let input = prepare_offline(&state, req)?;
+    let progress = jobs::Progress::yielding_to(state.scheduler.clone());
+    let job = state
+        .jobs
+        .create(input.session.session_id(), input.priority, progress);
+    let view = job.view();
+
+    tokio::spawn(async move {
+        let _slot = tokio::select! {
+            slot = state.scheduler.acquire(input.priority) => slot,
+            () = job.progress().cancelled() => return,
+        };
+        if !job.start() {
+            return;
+        }
+        let run = run_offline(&state, input, job.progress()).await;
+        job.finish(run, |run| run.commit(&state));
+    });
+
+    Ok((StatusCode::ACCEPTED, Json(view)))
+}
@@ -0,0 +1,517 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+use axum::extract::{Path as UrlPath, Query, State};
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::{Deserialize, Serialize};
+
+use crate::clock::current_epoch_ms;
+use crate::error::AppError;
+use crate::state::{labeled_timeline, CachedResponse, ServerState};
+use crate::track::{parse_speaker_id, Track};
+use crate::validation::ValidatedJson;
+use crate::{accounting, analytics, clustering, recluster, report, snapshot, summary, timeline};
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ReclusterRequest {
+    pub threshold: Option<f32>,
+    pub max_speakers: Option<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct SummaryRequest {
+    /// Speaker id of the interviewer; defaults to the first speaker.
+    pub interviewer: Option<String>,
+    /// Display names by speaker id, echoed into the summary.
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    pub min_silence_ms: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TimelineRangeQuery {
+    from_ms: i64,
+    to_ms: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TimelineDeletionResponse {
+    session_id: String,
+    from_ms: i64,
+    to_ms: i64,
+    tracks_removed: usize,
+    tracks_trimmed: usize,
+    speech_removed_ms: i64,
+    embeddings_removed: usize,
+    speakers_removed: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErasureReceipt {
+    receipt_id: String,
+    session_id: String,
+    speaker_id: String,
+    embeddings_removed: usize,
+    voiceprint_removed: bool,
+    erased_at_ms: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CancelResponse {
+    session_id: String,
+    queued_windows_cancelled: usize,
+    in_flight_window: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportTable {
+    #[default]
+    Tracks,
+    Speakers,
+    Config,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    format: Option<ExportFormat>,
+    /// CSV holds one table per response; JSON always carries all of them.
+    table: Option<ExportTable>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExportResponse {
+    session_id: String,
+    tracks: Vec<Track>,
+    speakers: Vec<report::SpeakerStats>,
+    config: snapshot::ConfigSnapshot,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AnalyticsQuery {
+    interviewer: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TalkRatioQuery {
+    window_ms: Option<i64>,
+    step_ms: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReclusterResponse {
+    session_id: String,
+    #[serde(flatten)]
+    result: recluster::Recluster,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SessionAudioResponse {
+    session_id: String,
+    audio: accounting::AudioAccounting,
+}
+
+/// Aborts the session's pending work, typically because the user stopped
+/// recording. Speakers and timeline are kept, and windows sent after the call
+/// are processed normally, without pre-roll from before it.
+pub async fn cancel_session(
+    State(state): State<Arc<ServerState>>,
+    UrlPath(session_id): UrlPath<String>,
+) -> Result<Json<CancelResponse>, AppError> {
+    let session = state
+        .sessions
+        .get(&session_id)
+        .ok_or_else(|| AppError::not_found(format!("unknown session: {session_id}")))?;
+
+    let cancellation = session.cancel();
+    Ok(Json(CancelResponse {
+        session_id,
+        queued_windows_cancelled: cancellation.queued_windows,
+        in_flight_window: cancellation.in_flight,
+    }))
+}
+
+/// Removes everything the sidecar holds about `[from_ms, to_ms)` of a
+/// session: timeline tracks are cut, segment embeddings overlapping the range
+/// are dropped, and cached responses that echo the range are discarded. Each
+/// affected speaker's voiceprint is rebuilt from its remaining embeddings, or
+/// removed from matching when none remain. The cut speech moves from covered
+/// to deleted audio, and results of finished offline jobs for the session
+/// are withdrawn. Analytics derive from the timeline and reflect the
+/// deletion immediately.
+pub async fn delete_timeline_range(
+    State(state): State<Arc<ServerState>>,
+    UrlPath(session_id): UrlPath<String>,
+    Query(query): Query<TimelineRangeQuery>,
+) -> Result<Json<TimelineDeletionResponse>, AppError> {
+    let TimelineRangeQuery { from_ms, to_ms } = query;
+    if from_ms < 0 || to_ms <= from_ms {
+        return Err(AppError::bad_request(
+            "from_ms must be >= 0 and to_ms > from_ms",
+        ));
+    }
+
+    let session = state
+        .sessions
+        .get(&session_id)
+        .ok_or_else(|| AppError::not_found(format!("unknown session: {session_id}")))?;
+
+    let scorer = state.scorer.clone();
+    let response = session
+        .call(move |session| {
+            session
+                .audio
+                .delete_range(&session.timeline, (from_ms, to_ms));
+            let removal = timeline::remove_range(&mut session.timeline, from_ms, to_ms);
+            // The pre-roll may hold audio from the deleted range.
+            session.tail = None;
+
+            let mut affected_speakers = Vec::new();
+            let embeddings_before = session.embeddings.len();
+            session.embeddings.retain(|stored| {
+                let hit = timeline::overlaps(stored.start_ms, stored.end_ms, from_ms, to_ms);
+                if hit {
+                    affected_speakers.push(stored.speaker_id);
+                }
+                !hit
+            });
+            let embeddings_removed = embeddings_before - session.embeddings.len();
+            affected_speakers.sort_unstable();
+            affected_speakers.dedup();
+
+            let mut speakers_removed = Vec::new();
+            for speaker_id in affected_speakers {
+                let remaining: Vec<&[f32]> = session
+                    .embeddings
+                    .iter()
+                    .filter(|stored| stored.speaker_id == speaker_id)
+                    .map(|stored| stored.embedding.as_slice())
+                    .collect();
+                if remaining.is_empty() {
+                    if session.manager.remove_speaker(speaker_id) {
+                        speakers_removed.push(format!("edge_spk_{speaker_id}"));
+                    }
+                } else {
+                    session
+                        .manager
+                        .set_speaker(speaker_id, clustering::centroid(&remaining));
+                }
+            }
+            session
+                .manager
+                .recount(&session.embeddings, &session.timeline, &scorer);
+
+            let echoes_range =
+                |cached: &CachedResponse| {
+                    cached.response.tracks.iter().any(|track| {
+                        timeline::overlaps(track.start_ms, track.end_ms, from_ms, to_ms)
+                    })
+                };
+            session
+                .idempotent_responses
+                .retain(|_, cached| !echoes_range(cached));
+            session
+                .window_fingerprints
+                .retain(|_, cached| !echoes_range(cached));
+
+            TimelineDeletionResponse {
+                session_id,
+                from_ms,
+                to_ms,
+                tracks_removed: removal.tracks_removed,
+                tracks_trimmed: removal.tracks_trimmed,
+                speech_removed_ms: removal.speech_removed_ms,
+                embeddings_removed,
+                speakers_removed,
+            }
+        })
+        .await?;
+    state.jobs.withdraw_results(&response.session_id);
+    Ok(Json(response))
+}
+
+/// Irreversibly erases a speaker's voice data from a session: every stored
+/// segment embedding, the voiceprint used for matching and the buffered
+/// pre-roll audio. Timeline tracks
+/// hold no biometric data and are kept; the speaker id is never reused, so
+/// later speech from the same person is enrolled as a new speaker.
+pub async fn erase_speaker_embeddings(
+    State(state): State<Arc<ServerState>>,
+    UrlPath((session_id, speaker)): UrlPath<(String, String)>,
+) -> Result<Json<ErasureReceipt>, AppError> {
+    let speaker_id = parse_speaker_id(&speaker)
+        .ok_or_else(|| AppError::bad_request(format!("invalid speaker id: {speaker}")))?;
+
+    let session = state
+        .sessions
+        .get(&session_id)
+        .ok_or_else(|| AppError::not_found(format!("unknown session: {session_id}")))?;
+
+    let receipt = session
+        .call(move |session| {
+            let embeddings_before = session.embeddings.len();
+            session
+                .embeddings
+                .retain(|stored| stored.speaker_id != speaker_id);
+            let embeddings_removed = embeddings_before - session.embeddings.len();
+            let voiceprint_removed = session.manager.remove_speaker(speaker_id);
+            // The pre-roll is raw audio that may hold the speaker's voice.
+            session.tail = None;
+
+            if embeddings_removed == 0 && !voiceprint_removed {
+                return Err(AppError::not_found(format!(
+                    "no voice data for edge_spk_{speaker_id} in session {session_id}"
+                )));
+            }
+
+            let erased_at_ms = current_epoch_ms();
+            let mut hasher = DefaultHasher::new();
+            (&session_id, speaker_id, embeddings_removed, erased_at_ms).hash(&mut hasher);
+
+            Ok(ErasureReceipt {
+                receipt_id: format!("erase-{:016x}", hasher.finish()),
+                session_id,
+                speaker_id: format!("edge_spk_{speaker_id}"),
+                embeddings_removed,
+                voiceprint_removed,
+                erased_at_ms,
+            })
+        })
+        .await??;
+    Ok(Json(receipt))
+}
+
+pub async fn session_analytics(
+    State(state): State<Arc<ServerState>>,
+    UrlPath(session_id): UrlPath<String>,
+    Query(query): Query<AnalyticsQuery>,
+) -> Result<Json<report::AnalyticsExport<analytics::AnalyticsResponse>>, AppError> {
+    let session = state
+        .sessions
+        .get(&session_id)
+        .ok_or_else(|| AppError::not_found(format!("unknown session: {session_id}")))?;
+
+    let response = session
+        .call(move |session| report::AnalyticsExport {
+            analytics: analytics::analyze(
+                &session_id,
+                &labeled_timeline(session),
+                query.interviewer.as_deref(),
+            ),
+            config: session.config.clone(),
+        })
+        .await?;
+    Ok(Json(response))
+}
+
+/// Re-clusters every segment the session has heard and returns only what
+/// changed, keeping speaker ids stable wherever the new clusters line up
+/// with the old ones.
+pub async fn recluster_session(
+    State(state): State<Arc<ServerState>>,
+    UrlPath(session_id): UrlPath<String>,
+    req: Option<ValidatedJson<ReclusterRequest>>,
+) -> Result<Json<ReclusterResponse>, AppError> {
+    let req = req.map(|ValidatedJson(req)| req).unwrap_or_default();
+    let session = state
+        .sessions
+        .get(&session_id)
+        .ok_or_else(|| AppError::not_found(format!("unknown session: {session_id}")))?;
+
+    let threshold = req
+        .threshold
+        .unwrap_or(state.config.threshold)
+        .clamp(0.0, 1.0);
+    let max_speakers = req.max_speakers.unwrap_or(state.config.max_speakers).max(1);
+    let mut input = session.call(|session| recluster::input(session)).await?;
+    let (embeddings, labels) = clustering::agglomerative_blocking(
+        std::mem::take(&mut input.embeddings),
+        threshold,
+        max_speakers,
+        state.scorer.clone(),
+        None,
+    )
+    .await;
+    input.embeddings = embeddings;
+    // Without a deadline clustering always finishes.
+    let labels = labels.unwrap_or_default();
+    let scorer = state.scorer.clone();
+    let result = session
+        .call(move |session| {
+            // Windows or deletions that landed meanwhile would be relabeled
+            // by clusters that never saw them.
+            if !recluster::is_current(session, &input) {
+                return Err(AppError::conflict(
+                    "session segments changed while reclustering, retry",
+                ));
+            }
+            let result = recluster::apply(session, &input, &labels);
+            session
+                .manager
+                .recount(&session.embeddings, &session.timeline, &scorer);
+            Ok(result)
+        })
+        .await??;
+    Ok(Json(ReclusterResponse { session_id, result }))
+}
+
+/// One consolidated document for the interview-feedback record: final
+/// timeline, speakers with roles, labels and talk time, interruptions,
+/// silences, analytics, audio accounting and quality warnings.
+pub async fn session_summary(
+    State(state): State<Arc<ServerState>>,
+    UrlPath(session_id): UrlPath<String>,
+    req: Option<ValidatedJson<SummaryRequest>>,
+) -> Result<Json<summary::Summary>, AppError> {
+    let req = req.map(|ValidatedJson(req)| req).unwrap_or_default();
+    let session = state
+        .sessions
+        .get(&session_id)
+        .ok_or_else(|| AppError::not_found(format!("unknown session: {session_id}")))?;
+
+    let min_silence_ms = req
+        .min_silence_ms
+        .unwrap_or(summary::DEFAULT_MIN_SILENCE_MS)
+        .max(1);
+    let response = session
+        .call(move |session| {
+            let options = summary::SummaryOptions {
+                interviewer: req.interviewer.as_deref(),
+                labels: &req.labels,
+                min_silence_ms,
+            };
+            summary::summarize(&session_id, session, options, current_epoch_ms())
+        })
+        .await?;
+    Ok(Json(response))
+}
+
+/// Running totals of where the session's received audio went, so QA can
+/// check that none was lost without a reason.
+pub async fn session_audio(
+    State(state): State<Arc<ServerState>>,
+    UrlPath(session_id): UrlPath<String>,
+) -> Result<Json<SessionAudioResponse>, AppError> {
+    let session = state
+        .sessions
+        .get(&session_id)
+        .ok_or_else(|| AppError::not_found(format!("unknown session: {session_id}")))?;
+
+    let audio = session.call(|session| session.audio).await?;
+    Ok(Json(SessionAudioResponse { session_id, audio }))
+}
+
+/// Exports the session timeline, per-speaker stats and the configuration
+/// snapshot the session ran with. `format=csv` returns the table picked by
+/// `table` (`tracks` by default) as a CSV download with the column sets
+/// documented in `report` and `snapshot`.
+pub async fn export_session(
+    State(state): State<Arc<ServerState>>,
+    UrlPath(session_id): UrlPath<String>,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, AppError> {
+    let session = state
+        .sessions
+        .get(&session_id)
+        .ok_or_else(|| AppError::not_found(format!("unknown session: {session_id}")))?;
+    let (tracks, config) = session
+        .call(|session| (labeled_timeline(session), session.config.clone()))
+        .await?;
+
+    let table = query.table.unwrap_or_default();
+    let (table_name, csv) = match query.format.unwrap_or_default() {
+        ExportFormat::Json => {
+            let speakers = report::speaker_stats(&tracks);
+            return Ok(Json(ExportResponse {
+                session_id,
+                tracks,
+                speakers,
+                config,
+            })
+            .into_response());
+        }
+        ExportFormat::Csv => match table {
+            ExportTable::Tracks => ("tracks", report::tracks_csv(&tracks)),
+            ExportTable::Speakers => (
+                "speakers",
+                report::speakers_csv(&report::speaker_stats(&tracks)),
+            ),
+            ExportTable::Config => ("config", snapshot::config_csv(&config)),
+        },
+    };
+
+    let file_stem: String = session_id
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{file_stem}-{table_name}.csv\""),
+            ),
+        ],
+        csv,
+    )
+        .into_response())
+}
+
+pub async fn session_talk_ratio(
+    State(state): State<Arc<ServerState>>,
+    UrlPath(session_id): UrlPath<String>,
+    Query(query): Query<TalkRatioQuery>,
+) -> Result<Json<report::AnalyticsExport<analytics::TalkRatioResponse>>, AppError> {
+    let window_ms = query.window_ms.unwrap_or(60_000);
+    let step_ms = query.step_ms.unwrap_or(window_ms);
+    let bounds = analytics::MIN_TALK_RATIO_MS..=analytics::MAX_TALK_RATIO_MS;
+    if !bounds.contains(&window_ms) || !bounds.contains(&step_ms) {
+        return Err(AppError::bad_request(format!(
+            "window_ms and step_ms must be between {} and {}",
+            bounds.start(),
+            bounds.end()
+        )));
+    }
+
+    let session = state
+        .sessions
+        .get(&session_id)
+        .ok_or_else(|| AppError::not_found(format!("unknown session: {session_id}")))?;
+
+    let response = session
+        .call(move |session| {
+            let analytics =
+                analytics::talk_ratio(&session_id, &labeled_timeline(session), window_ms, step_ms)
+                    .map_err(|error| {
+                        AppError::bad_request(format!(
+                            "step_ms {step_ms} would produce {} windows, more than {}",
+                            error.buckets,
+                            analytics::MAX_TALK_RATIO_BUCKETS
+                        ))
+                    })?;
+            Ok::<_, AppError>(report::AnalyticsExport {
+                analytics,
+                config: session.config.clone(),
+            })
+        })
+        .await??;
+    Ok(Json(response))
+}
@@ -0,0 +1,159 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+
+use crate::error::AppError;
+use crate::models::ModelSet;
+use crate::readiness;
+use crate::state::{ModelReloadState, ModelReloadStatus, ServerState};
+use crate::validation::ValidatedJson;
+
+#[derive(Debug, Serialize)]
+pub struct HealthResponse {
+    status: &'static str,
+    uptime_ms: u128,
+    segmentation_model: String,
+    embedding_model: String,
+    model_generation: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReadinessResponse {
+    status: &'static str,
+    checks: Vec<readiness::Check>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ActiveModels {
+    generation: u64,
+    segmentation_model: String,
+    embedding_model: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ModelsResponse {
+    active: ActiveModels,
+    reload: ModelReloadStatus,
+    sessions_by_generation: BTreeMap<u64, usize>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ModelReloadRequest {
+    pub segmentation_model: PathBuf,
+    pub embedding_model: PathBuf,
+}
+
+pub async fn health(State(state): State<Arc<ServerState>>) -> Json<HealthResponse> {
+    let models = state.models.read().await.clone();
+    Json(HealthResponse {
+        status: "ok",
+        uptime_ms: state.started_at.elapsed().as_millis(),
+        segmentation_model: models.segmentation_model.to_string_lossy().to_string(),
+        embedding_model: models.embedding_model.to_string_lossy().to_string(),
+        model_generation: models.generation,
+    })
+}
+
+pub async fn ready(State(state): State<Arc<ServerState>>) -> (StatusCode, Json<ReadinessResponse>) {
+    let models = state.models.read().await.clone();
+    let checks = readiness::run_checks(
+        &models,
+        &state.config.writable_dirs,
+        state.config.min_free_disk_bytes,
+    );
+
+    if checks.iter().all(|check| check.ok) {
+        (
+            StatusCode::OK,
+            Json(ReadinessResponse {
+                status: "ready",
+                checks,
+            }),
+        )
+    } else {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(ReadinessResponse {
+                status: "not_ready",
+                checks,
+            }),
+        )
+    }
+}
+
+pub async fn list_models(State(state): State<Arc<ServerState>>) -> Json<ModelsResponse> {
+    let models = state.models.read().await.clone();
+    let reload = state.model_reload.lock().await.clone();
+
+    let mut sessions_by_generation = BTreeMap::new();
+    for (_, session) in state.sessions.all() {
+        *sessions_by_generation
+            .entry(session.model_generation())
+            .or_default() += 1;
+    }
+
+    Json(ModelsResponse {
+        active: ActiveModels {
+            generation: models.generation,
+            segmentation_model: models.segmentation_model.to_string_lossy().to_string(),
+            embedding_model: models.embedding_model.to_string_lossy().to_string(),
+        },
+        reload,
+        sessions_by_generation,
+    })
+}
+
+/// Loads and warms a new model pair in the background while the current pair
+/// keeps serving. Once warm it becomes active for sessions created from then
+/// on; sessions already running stay pinned to the pair they started with.
+pub async fn reload_models(
+    State(state): State<Arc<ServerState>>,
+    ValidatedJson(req): ValidatedJson<ModelReloadRequest>,
+) -> Result<(StatusCode, Json<ModelReloadStatus>), AppError> {
+    let generation = {
+        let mut reload = state.model_reload.lock().await;
+        if reload.state == ModelReloadState::Loading {
+            return Err(AppError::conflict("a model reload is already in progress"));
+        }
+        let generation = state.next_model_generation.fetch_add(1, Ordering::SeqCst);
+        *reload = ModelReloadStatus {
+            state: ModelReloadState::Loading,
+            target_generation: Some(generation),
+            error: None,
+        };
+        generation
+    };
+
+    let task_state = state.clone();
+    tokio::spawn(async move {
+        let loaded = tokio::task::spawn_blocking(move || {
+            let models = ModelSet::load(generation, req.segmentation_model, req.embedding_model)?;
+            models.warm_up()?;
+            Ok::<_, String>(models)
+        })
+        .await
+        .unwrap_or_else(|error| Err(format!("model load task failed: {error}")));
+
+        let mut reload = task_state.model_reload.lock().await;
+        match loaded {
+            Ok(models) => {
+                *task_state.models.write().await = Arc::new(models);
+                *reload = ModelReloadStatus::default();
+                println!("pyannote-rs sidecar switched to model generation {generation}");
+            }
+            Err(error) => {
+                reload.state = ModelReloadState::Failed;
+                reload.error = Some(error);
+            }
+        }
+    });
+
+    let status = state.model_reload.lock().await.clone();
+    Ok((StatusCode::ACCEPTED, Json(status)))
+}
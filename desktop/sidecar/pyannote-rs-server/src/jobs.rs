@@ -0,0 +1,382 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+use tokio::sync::Notify;
+
+use crate::clock::current_epoch_ms;
+use crate::error::AppError;
+use crate::handlers::offline::OfflineDiarizeResponse;
+use crate::scheduler::{Priority, Scheduler};
+
+/// Progress, cancellation and live-priority yielding shared between a job and
+/// the pipeline running it. The CLI passes a default value, which never
+/// cancels or yields.
+#[derive(Debug, Default)]
+pub struct Progress {
+    permille: AtomicU32,
+    cancelled: AtomicBool,
+    cancel_notify: Notify,
+    scheduler: Option<Arc<Scheduler>>,
+}
+
+impl Progress {
+    /// Progress for work that pauses between segments while live windows
+    /// are being diarized.
+    pub fn yielding_to(scheduler: Arc<Scheduler>) -> Self {
+        Self {
+            scheduler: Some(scheduler),
+            ..Self::default()
+        }
+    }
+
+    pub async fn yield_to_live(&self) {
+        if let Some(scheduler) = &self.scheduler {
+            scheduler.yield_to_live().await;
+        }
+    }
+
+    pub fn set(&self, fraction: f64) {
+        let permille = (fraction.clamp(0.0, 1.0) * 1000.0).round() as u32;
+        self.permille.fetch_max(permille, Ordering::Relaxed);
+    }
+
+    pub fn fraction(&self) -> f64 {
+        self.permille.load(Ordering::Relaxed) as f64 / 1000.0
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+        self.cancel_notify.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Resolves once [`Self::cancel`] has been called.
+    pub async fn cancelled(&self) {
+        let mut notified = std::pin::pin!(self.cancel_notify.notified());
+        notified.as_mut().enable();
+        if self.is_cancelled() {
+            return;
+        }
+        notified.await;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    fn is_finished(self) -> bool {
+        matches!(self, Self::Succeeded | Self::Failed | Self::Cancelled)
+    }
+}
+
+#[derive(Debug)]
+struct Outcome {
+    status: JobStatus,
+    finished_at_ms: Option<i64>,
+    result: Option<OfflineDiarizeResponse>,
+    /// Set when the result was dropped because part of the session it
+    /// describes was deleted.
+    result_withdrawn_at_ms: Option<i64>,
+    error: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct Job {
+    id: String,
+    session_id: String,
+    priority: Priority,
+    created_at_ms: i64,
+    progress: Progress,
+    outcome: Mutex<Outcome>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JobView {
+    job_id: String,
+    session_id: String,
+    priority: Priority,
+    status: JobStatus,
+    /// Fraction of the recording processed, in [0, 1].
+    progress: f64,
+    created_at_ms: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    finished_at_ms: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<OfflineDiarizeResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result_withdrawn_at_ms: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Job {
+    pub fn progress(&self) -> &Progress {
+        &self.progress
+    }
+
+    /// Moves a queued job to running. Returns false if it was cancelled
+    /// while queued.
+    pub fn start(&self) -> bool {
+        let mut outcome = self.outcome.lock().unwrap();
+        if outcome.status != JobStatus::Queued {
+            return false;
+        }
+        outcome.status = JobStatus::Running;
+        true
+    }
+
+    /// Records how the job ended. A successful `run` is passed to `publish`,
+    /// which makes it visible, e.g. by creating its session. Publishing
+    /// happens under the lock [`Self::cancel`] takes, so a cancellation
+    /// either prevents it or fails because the job already finished.
+    pub fn finish<T>(
+        &self,
+        run: Result<T, AppError>,
+        publish: impl FnOnce(T) -> Result<OfflineDiarizeResponse, AppError>,
+    ) {
+        let mut outcome = self.outcome.lock().unwrap();
+        outcome.finished_at_ms = Some(current_epoch_ms());
+        if self.progress.is_cancelled() {
+            outcome.status = JobStatus::Cancelled;
+            return;
+        }
+        match run.and_then(publish) {
+            Ok(response) => {
+                self.progress.set(1.0);
+                outcome.status = JobStatus::Succeeded;
+                outcome.result = Some(response);
+            }
+            Err(error) => {
+                outcome.status = JobStatus::Failed;
+                outcome.error = Some(error.message);
+            }
+        }
+    }
+
+    /// Requests cancellation. A queued job gives up its place in the
+    /// scheduler; a running job stops at its next segment. Neither creates
+    /// its session.
+    pub fn cancel(&self) -> Result<(), AppError> {
+        let mut outcome = self.outcome.lock().unwrap();
+        if outcome.status.is_finished() {
+            return Err(AppError::conflict(format!(
+                "job already finished: {}",
+                self.id
+            )));
+        }
+        self.progress.cancel();
+        if outcome.status == JobStatus::Queued {
+            outcome.status = JobStatus::Cancelled;
+            outcome.finished_at_ms = Some(current_epoch_ms());
+        }
+        Ok(())
+    }
+
+    pub fn view(&self) -> JobView {
+        let outcome = self.outcome.lock().unwrap();
+        JobView {
+            job_id: self.id.clone(),
+            session_id: self.session_id.clone(),
+            priority: self.priority,
+            status: outcome.status,
+            progress: self.progress.fraction(),
+            created_at_ms: self.created_at_ms,
+            finished_at_ms: outcome.finished_at_ms,
+            result: outcome.result.clone(),
+            result_withdrawn_at_ms: outcome.result_withdrawn_at_ms,
+            error: outcome.error.clone(),
+        }
+    }
+}
+
+/// In-memory job table. Finished jobs are kept for `retention_ms` so clients
+/// can collect the result, then dropped on the next submission.
+#[derive(Debug)]
+pub struct JobRegistry {
+    retention_ms: i64,
+    jobs: Mutex<HashMap<String, Arc<Job>>>,
+}
+
+impl JobRegistry {
+    pub fn new(retention_ms: i64) -> Self {
+        Self {
+            retention_ms,
+            jobs: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn create(&self, session_id: &str, priority: Priority, progress: Progress) -> Arc<Job> {
+        let now_ms = current_epoch_ms();
+        let job = Arc::new(Job {
+            id: uuid::Uuid::new_v4().to_string(),
+            session_id: session_id.to_string(),
+            priority,
+            created_at_ms: now_ms,
+            progress,
+            outcome: Mutex::new(Outcome {
+                status: JobStatus::Queued,
+                finished_at_ms: None,
+                result: None,
+                result_withdrawn_at_ms: None,
+                error: None,
+            }),
+        });
+
+        let mut jobs = self.jobs.lock().unwrap();
+        jobs.retain(|_, job| {
+            let finished_at_ms = job.outcome.lock().unwrap().finished_at_ms;
+            finished_at_ms.is_none_or(|finished_at_ms| now_ms - finished_at_ms <= self.retention_ms)
+        });
+        jobs.insert(job.id.clone(), job.clone());
+        job
+    }
+
+    pub fn get(&self, job_id: &str) -> Option<Arc<Job>> {
+        self.jobs.lock().unwrap().get(job_id).cloned()
+    }
+
+    /// Drops the results of finished jobs that created `session_id`, after
+    /// data they hold was deleted from the session.
+    pub fn withdraw_results(&self, session_id: &str) {
+        let now_ms = current_epoch_ms();
+        let jobs = self.jobs.lock().unwrap();
+        for job in jobs.values().filter(|job| job.session_id == session_id) {
+            let mut outcome = job.outcome.lock().unwrap();
+            if outcome.result.take().is_some() {
+                outcome.result_withdrawn_at_ms = Some(now_ms);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{accounting, analytics, timebase};
+
+    fn response(session_id: &str) -> OfflineDiarizeResponse {
+        OfflineDiarizeResponse {
+            session_id: session_id.to_string(),
+            speaker_count: 0,
+            time_unit: timebase::TimeUnit::default(),
+            tracks: Vec::new(),
+            analytics: analytics::analyze(session_id, &[], None),
+            audio: accounting::AudioAccounting::default(),
+            warnings: Vec::new(),
+            profile: None,
+        }
+    }
+
+    fn status(job: &Job) -> JobStatus {
+        job.view().status
+    }
+
+    #[tokio::test]
+    async fn cancelling_a_queued_job_frees_its_scheduler_place() {
+        let scheduler = Arc::new(Scheduler::new(1));
+        let held = scheduler.acquire(Priority::Offline).await;
+        let registry = JobRegistry::new(60_000);
+        let job = registry.create("s", Priority::Offline, Progress::default());
+
+        let waiter = tokio::spawn({
+            let scheduler = scheduler.clone();
+            let job = job.clone();
+            async move {
+                tokio::select! {
+                    _slot = scheduler.acquire(Priority::Offline) => false,
+                    () = job.progress().cancelled() => true,
+                }
+            }
+        });
+        tokio::task::yield_now().await;
+        job.cancel().unwrap();
+
+        let gave_up = tokio::time::timeout(Duration::from_millis(200), waiter).await;
+        assert!(gave_up.unwrap().unwrap());
+        assert_eq!(status(&job), JobStatus::Cancelled);
+        assert!(!job.start());
+        drop(held);
+    }
+
+    #[test]
+    fn cancelling_a_running_job_skips_publishing() {
+        let registry = JobRegistry::new(60_000);
+        let job = registry.create("s", Priority::Offline, Progress::default());
+        assert!(job.start());
+        job.cancel().unwrap();
+
+        job.finish(Ok(()), |()| -> Result<_, AppError> {
+            panic!("a cancelled job must not publish")
+        });
+        assert_eq!(status(&job), JobStatus::Cancelled);
+        assert!(job.view().result.is_none());
+    }
+
+    #[test]
+    fn a_finished_job_can_no_longer_be_cancelled() {
+        let registry = JobRegistry::new(60_000);
+        let job = registry.create("s", Priority::Offline, Progress::default());
+        job.start();
+        job.finish(Ok(()), |()| Ok(response("s")));
+
+        assert_eq!(job.cancel().unwrap_err().status, 409);
+        assert_eq!(status(&job), JobStatus::Succeeded);
+        assert_eq!(job.view().progress, 1.0);
+        assert!(job.view().result.is_some());
+    }
+
+    #[test]
+    fn a_failed_publish_fails_the_job() {
+        let registry = JobRegistry::new(60_000);
+        let job = registry.create("s", Priority::Offline, Progress::default());
+        job.start();
+        job.finish(Ok(()), |()| {
+            Err(AppError::conflict("session already exists: s"))
+        });
+
+        let view = job.view();
+        assert_eq!(view.status, JobStatus::Failed);
+        assert_eq!(view.error.as_deref(), Some("session already exists: s"));
+    }
+
+    #[test]
+    fn withdrawn_results_only_touch_the_session() {
+        let registry = JobRegistry::new(60_000);
+        let first = registry.create("a", Priority::Offline, Progress::default());
+        let second = registry.create("b", Priority::Offline, Progress::default());
+        for (job, session_id) in [(&first, "a"), (&second, "b")] {
+            job.start();
+            job.finish(Ok(()), |()| Ok(response(session_id)));
+        }
+
+        registry.withdraw_results("a");
+        assert!(first.view().result.is_none());
+        assert!(first.view().result_withdrawn_at_ms.is_some());
+        assert!(second.view().result.is_some());
+    }
+
+    #[test]
+    fn job_ids_are_uuids() {
+        let registry = JobRegistry::new(60_000);
+        let first = registry.create("s", Priority::Offline, Progress::default());
+        let second = registry.create("s", Priority::Offline, Progress::default());
+        assert!(uuid::Uuid::parse_str(&first.view().job_id).is_ok());
+        assert_ne!(first.view().job_id, second.view().job_id);
+        assert!(registry.get(&second.view().job_id).is_some());
+    }
+}
@@ -0,0 +1,256 @@
+mod accounting;
+mod admission;
+mod analytics;
+mod assignment;
+mod audit;
+mod calibration;
+mod cli;
+mod clock;
+mod clustering;
+mod error;
+mod estimate;
+mod events;
+mod gating;
+mod handlers;
+mod jobs;
+mod models;
+mod postprocess;
+mod profile;
+mod readiness;
+mod recluster;
+mod report;
+mod scheduler;
+mod scoring;
+mod server;
+mod sessions;
+mod snapshot;
+mod speakers;
+mod spectrum;
+mod state;
+mod storage;
+mod summary;
+mod timebase;
+mod timeline;
+mod track;
+mod validation;
+
+use std::path::Path;
+use std::sync::atomic::AtomicU64;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use axum::routing::{delete, get, post};
+use axum::Router;
+use cli::{exe_dir, resolve_model_path};
+use models::ModelSet;
+use scoring::Scorer;
+use state::{Config, ModelReloadStatus};
+use tokio::sync::{broadcast, Mutex, RwLock};
+
+pub use cli::{run, LatencyBudget, ServeArgs};
+pub use state::ServerState;
+
+/// Loads and warms the models, creates the state directory and starts the
+/// background watchers (clock jumps, idle sessions). Must run inside a Tokio
+/// runtime. Models default to
+/// the `models` directory next to the running executable, which for a host
+/// application is its own binary, so hosts usually set both paths.
+/// Listener and HTTP tuning options are ignored here.
+pub async fn build_state(args: &ServeArgs) -> Result<Arc<ServerState>, Box<dyn std::error::Error>> {
+    let exe_dir = exe_dir()?;
+
+    let segmentation_model = resolve_model_path(
+        args.segmentation_model.clone(),
+        &exe_dir,
+        "segmentation-3.0.onnx",
+    );
+    let embedding_model = resolve_model_path(
+        args.embedding_model.clone(),
+        &exe_dir,
+        "wespeaker_en_voxceleb_CAM++.onnx",
+    );
+
+    let models = ModelSet::load(1, segmentation_model, embedding_model)?;
+
+    let scorer = match &args.scoring_config {
+        Some(path) => Scorer::load(path)?,
+        None => Scorer::cosine(),
+    };
+
+    let postprocess = match &args.postprocess_config {
+        Some(path) => postprocess::Pipeline::load(path)?,
+        None => postprocess::Pipeline::default(),
+    };
+
+    let state_dir = args.state_dir();
+    std::fs::create_dir_all(&state_dir).map_err(|error| {
+        format!(
+            "failed to create state directory {}: {error}",
+            state_dir.display()
+        )
+    })?;
+    let audit_log = args.audit_log_path();
+    let profile_trace_dir = args.profile_trace_dir();
+
+    let mut writable_dirs = vec![("state_dir".to_string(), state_dir)];
+    if let Some(dir) = audit_log.as_deref().and_then(Path::parent) {
+        writable_dirs.push(("audit_log_dir".to_string(), dir.to_path_buf()));
+    }
+    if let Some(dir) = &profile_trace_dir {
+        writable_dirs.push(("profile_trace_dir".to_string(), dir.clone()));
+    }
+
+    let session_ttl_ms = (Duration::from_secs(args.session_ttl_sec.max(60)).as_millis()) as i64;
+    let config = Config {
+        max_speakers: args.max_speakers.max(1),
+        threshold: args.threshold.clamp(0.0, 1.0),
+        session_ttl_ms,
+        idempotency_ttl_ms: (Duration::from_secs(args.idempotency_ttl_sec).as_millis()) as i64,
+        pre_roll_ms: args.pre_roll_ms as i64,
+        embedding_gate: gating::EmbeddingGate {
+            min_effective_ms: args.min_embedding_ms as i64,
+            min_rms_hz: args.min_embedding_hz,
+        },
+        retire_silent_speakers_ms: (args.retire_silent_speakers_sec > 0)
+            .then(|| (Duration::from_secs(args.retire_silent_speakers_sec).as_millis()) as i64),
+        latency_budgets: args.latency_budgets.iter().cloned().collect(),
+        postprocess,
+        writable_dirs,
+        min_free_disk_bytes: args.min_free_disk_mb * 1024 * 1024,
+        profile: args.profile,
+        profile_trace_dir,
+        strict_validation: args.strict_validation,
+    };
+
+    let state = Arc::new(ServerState {
+        config,
+        started_at: Instant::now(),
+        scorer,
+        models: RwLock::new(Arc::new(models)),
+        next_model_generation: AtomicU64::new(2),
+        model_reload: Mutex::new(ModelReloadStatus::default()),
+        events: broadcast::channel(64).0,
+        clock: clock::ClockMonitor::new(
+            (Duration::from_secs(args.clock_jump_threshold_sec.max(2)).as_millis()) as i64,
+        ),
+        sessions: sessions::SessionRegistry::default(),
+        jobs: jobs::JobRegistry::new(session_ttl_ms),
+        scheduler: Arc::new(scheduler::Scheduler::new(args.offline_workers)),
+    });
+
+    tokio::spawn(clock::watch(state.clone()));
+    if args.inactivity_warning_sec > 0 {
+        let idle_warning_ms = (Duration::from_secs(args.inactivity_warning_sec).as_millis()) as i64;
+        tokio::spawn(events::watch_inactivity(state.clone(), idle_warning_ms));
+    }
+
+    Ok(state)
+}
+
+/// The diarization API over `state`, wrapped in the audit layer when
+/// `args.audit_log` is set. Host applications can mount it with
+/// `Router::nest` or merge it into their own router.
+pub fn build_router(
+    state: Arc<ServerState>,
+    args: &ServeArgs,
+) -> Result<Router, Box<dyn std::error::Error>> {
+    let mut app = Router::new()
+        .route("/health", get(handlers::health))
+        .route("/ready", get(handlers::ready))
+        .route("/diarize", post(handlers::diarize))
+        .route("/diarize/offline", post(handlers::diarize_offline))
+        .route("/diarize/offline/jobs", post(handlers::submit_offline_job))
+        .route("/jobs/{job_id}", get(handlers::get_job))
+        .route("/jobs/{job_id}/cancel", post(handlers::cancel_job))
+        .route("/calibrate", post(handlers::calibrate))
+        .route("/estimate_speakers", post(handlers::estimate_speakers))
+        .route("/models", get(handlers::list_models))
+        .route("/models/reload", post(handlers::reload_models))
+        .route("/events", get(events::subscribe))
+        .route(
+            "/sessions/{session_id}/cancel",
+            post(handlers::cancel_session),
+        )
+        .route(
+            "/sessions/{session_id}/timeline",
+            delete(handlers::delete_timeline_range),
+        )
+        .route(
+            "/sessions/{session_id}/speakers/{speaker_id}/embeddings",
+            delete(handlers::erase_speaker_embeddings),
+        )
+        .route(
+            "/sessions/{session_id}/analytics",
+            get(handlers::session_analytics),
+        )
+        .route(
+            "/sessions/{session_id}/export",
+            get(handlers::export_session),
+        )
+        .route("/sessions/{session_id}/audio", get(handlers::session_audio))
+        .route(
+            "/sessions/{session_id}/recluster",
+            post(handlers::recluster_session),
+        )
+        .route(
+            "/sessions/{session_id}/summary",
+            post(handlers::session_summary),
+        )
+        .route(
+            "/sessions/{session_id}/analytics/talk_ratio",
+            get(handlers::session_talk_ratio),
+        )
+        .with_state(state);
+
+    if let Some(path) = args.audit_log_path() {
+        let log = audit::AuditLog::open(path, args.audit_log_max_bytes, args.audit_log_max_files)
+            .map_err(|error| format!("failed to open audit log: {error}"))?;
+        app = app.layer(axum::middleware::from_fn_with_state(
+            Arc::new(log),
+            audit::record,
+        ));
+    }
+    Ok(app)
+}
+
+async fn serve(args: ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let state = build_state(&args).await?;
+    let app = build_router(state, &args)?;
+
+    let listeners = server::bind(&args.hosts, args.port).await?;
+    let mut addresses = Vec::with_capacity(listeners.len());
+    for listener in &listeners {
+        let addr = listener.local_addr()?;
+        println!("pyannote-rs sidecar listening on http://{addr}");
+        addresses.push(addr.to_string());
+    }
+    // Handshake for the desktop app: one JSON line with every bound address,
+    // which carries the actual port when `--port 0` was given.
+    println!(
+        "{}",
+        serde_json::json!({ "event": "listening", "pid": std::process::id(), "addresses": addresses })
+    );
+
+    let tuning = server::HttpTuning {
+        http2: !args.http1_only,
+        http1_keep_alive: args.keep_alive,
+        idle_timeout: (args.idle_timeout_sec > 0)
+            .then(|| Duration::from_secs(args.idle_timeout_sec)),
+        http2_max_concurrent_streams: args.http2_max_concurrent_streams.max(1),
+        http2_keep_alive_interval: (args.http2_keep_alive_interval_sec > 0)
+            .then(|| Duration::from_secs(args.http2_keep_alive_interval_sec)),
+    };
+
+    let limiter = admission::ConnectionLimiter::new(admission::ConnectionLimits {
+        max_open: args.max_connections_per_ip,
+        rate_per_sec: args.connection_rate_per_ip.max(0.0),
+        burst: args.connection_burst_per_ip,
+    });
+
+    server::serve(listeners, app, tuning, limiter, async {
+        let _ = tokio::signal::ctrl_c().await;
+    })
+    .await;
+
+    Ok(())
+}
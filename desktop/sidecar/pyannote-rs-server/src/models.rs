@@ -0,0 +1,71 @@
+use std::path::PathBuf;
+
+use pyannote_rs::EmbeddingExtractor;
+use tokio::sync::Mutex;
+
+/// One segmentation/embedding model pair. Sessions hold an `Arc` to the set
+/// they started on, so a swap only affects sessions created afterwards.
+#[derive(Debug)]
+pub struct ModelSet {
+    pub generation: u64,
+    pub segmentation_model: PathBuf,
+    pub embedding_model: PathBuf,
+    pub extractor: Mutex<EmbeddingExtractor>,
+}
+
+impl ModelSet {
+    pub fn load(
+        generation: u64,
+        segmentation_model: PathBuf,
+        embedding_model: PathBuf,
+    ) -> Result<Self, String> {
+        if !segmentation_model.exists() {
+            return Err(format!(
+                "segmentation model not found: {}",
+                segmentation_model.to_string_lossy()
+            ));
+        }
+        if !embedding_model.exists() {
+            return Err(format!(
+                "embedding model not found: {}",
+                embedding_model.to_string_lossy()
+            ));
+        }
+
+        let extractor = EmbeddingExtractor::new(&embedding_model)
+            .map_err(|error| format!("failed to initialize embedding extractor: {error}"))?;
+
+        Ok(Self {
+            generation,
+            segmentation_model,
+            embedding_model,
+            extractor: Mutex::new(extractor),
+        })
+    }
+
+    /// Runs both models once on a synthetic tone so session creation, graph
+    /// optimization and allocator growth happen before live traffic arrives.
+    /// Blocking; call from a blocking task.
+    pub fn warm_up(&self) -> Result<(), String> {
+        let sample_rate = 16_000u32;
+        let samples: Vec<i16> = (0..sample_rate * 2)
+            .map(|index| {
+                let phase = index as f32 * 220.0 * std::f32::consts::TAU / sample_rate as f32;
+                (phase.sin() * 3000.0) as i16
+            })
+            .collect();
+
+        let segments = pyannote_rs::get_segments(&samples, sample_rate, &self.segmentation_model)
+            .map_err(|error| format!("segmentation warm-up failed: {error}"))?;
+        for segment in segments {
+            segment.map_err(|error| format!("segmentation warm-up failed: {error}"))?;
+        }
+
+        self.extractor
+            .blocking_lock()
+            .compute(&samples)
+            .map_err(|error| format!("embedding warm-up failed: {error}"))?
+            .for_each(drop);
+        Ok(())
+    }
+}
@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::track::{merge_adjacent_tracks, Track, DEFAULT_MERGE_GAP_MS};
+
+/// One post-processing stage. Stages run in the order they are declared.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Step {
+    /// Joins consecutive tracks of the same speaker separated by at most
+    /// `max_gap_ms`.
+    Merge { max_gap_ms: i64 },
+    /// Drops tracks shorter than `min_ms`.
+    MinDuration { min_ms: i64 },
+    /// Relabels a track of at most `max_island_ms` to its neighbours' speaker
+    /// when the tracks on both sides belong to the same other speaker.
+    Smoothing { max_island_ms: i64 },
+    /// Widens every track by `collar_ms` on each side, clamped to the window.
+    Collar { collar_ms: i64 },
+    /// Renames speaker ids; ids missing from `map` are left untouched. Only
+    /// responses and exports are renamed, after every other step whatever its
+    /// position; sessions keep the canonical `edge_spk_N` ids that matching,
+    /// erasure and reclustering work with.
+    Relabel { map: HashMap<String, String> },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Pipeline {
+    pub steps: Vec<Step>,
+}
+
+impl Default for Pipeline {
+    fn default() -> Self {
+        Self {
+            steps: vec![Step::Merge {
+                max_gap_ms: DEFAULT_MERGE_GAP_MS,
+            }],
+        }
+    }
+}
+
+impl Pipeline {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let raw = std::fs::read_to_string(path).map_err(|error| {
+            format!(
+                "cannot read post-processing config {}: {error}",
+                path.to_string_lossy()
+            )
+        })?;
+        let pipeline: Self = serde_json::from_str(&raw).map_err(|error| {
+            format!(
+                "invalid post-processing config {}: {error}",
+                path.to_string_lossy()
+            )
+        })?;
+
+        for step in &pipeline.steps {
+            let valid = match step {
+                Step::Merge { max_gap_ms } => *max_gap_ms >= 0,
+                Step::MinDuration { min_ms } => *min_ms >= 0,
+                Step::Smoothing { max_island_ms } => *max_island_ms >= 0,
+                Step::Collar { collar_ms } => *collar_ms >= 0,
+                Step::Relabel { map } => map.values().all(|label| !label.trim().is_empty()),
+            };
+            if !valid {
+                return Err(format!("invalid post-processing step: {step:?}"));
+            }
+        }
+        Ok(pipeline)
+    }
+
+    /// Runs every step except `Relabel`, see [`Pipeline::relabel`].
+    pub fn apply(
+        &self,
+        mut tracks: Vec<Track>,
+        window_start_ms: i64,
+        window_end_ms: i64,
+    ) -> Vec<Track> {
+        tracks.sort_by(|a, b| a.start_ms.cmp(&b.start_ms).then(a.end_ms.cmp(&b.end_ms)));
+        for step in &self.steps {
+            tracks = match step {
+                Step::Merge { max_gap_ms } => merge_adjacent_tracks(tracks, *max_gap_ms),
+                Step::MinDuration { min_ms } => tracks
+                    .into_iter()
+                    .filter(|track| track.duration_ms >= *min_ms)
+                    .collect(),
+                Step::Smoothing { max_island_ms } => smooth(tracks, *max_island_ms),
+                Step::Collar { collar_ms } => tracks
+                    .into_iter()
+                    .map(|track| widen(track, *collar_ms, window_start_ms, window_end_ms))
+                    .collect(),
+                Step::Relabel { .. } => tracks,
+            };
+        }
+        tracks
+    }
+
+    /// Gap the session timeline joins tracks across: that of the last
+    /// `Merge` step, or none without one.
+    pub fn merge_gap_ms(&self) -> i64 {
+        self.steps
+            .iter()
+            .rev()
+            .find_map(|step| match step {
+                Step::Merge { max_gap_ms } => Some(*max_gap_ms),
+                _ => None,
+            })
+            .unwrap_or(0)
+    }
+
+    /// Display name of a canonical speaker id after every `Relabel` step.
+    pub fn label<'a>(&'a self, speaker_id: &'a str) -> &'a str {
+        self.steps
+            .iter()
+            .fold(speaker_id, |speaker_id, step| match step {
+                Step::Relabel { map } => map.get(speaker_id).map_or(speaker_id, String::as_str),
+                _ => speaker_id,
+            })
+    }
+
+    /// Renames the speakers of tracks on their way out of the sidecar.
+    pub fn relabel(&self, tracks: &mut [Track]) {
+        for track in tracks {
+            let label = self.label(&track.speaker_id);
+            if label != track.speaker_id {
+                track.speaker_id = label.to_string();
+            }
+        }
+    }
+}
+
+fn smooth(mut tracks: Vec<Track>, max_island_ms: i64) -> Vec<Track> {
+    for index in 1..tracks.len().saturating_sub(1) {
+        let neighbour = &tracks[index - 1].speaker_id;
+        if tracks[index].duration_ms <= max_island_ms
+            && tracks[index + 1].speaker_id == *neighbour
+            && tracks[index].speaker_id != *neighbour
+        {
+            tracks[index].speaker_id = neighbour.clone();
+        }
+    }
+    tracks
+}
+
+fn widen(mut track: Track, collar_ms: i64, window_start_ms: i64, window_end_ms: i64) -> Track {
+    let start_ms = (track.start_ms - collar_ms).max(window_start_ms);
+    let end_ms = (track.end_ms + collar_ms).min(window_end_ms).max(start_ms);
+    track.local_start_ms = (track.local_start_ms - (track.start_ms - start_ms)).max(0);
+    track.local_end_ms += end_ms - track.end_ms;
+    let collar_s = collar_ms as f64 / 1000.0;
+    track.exact_start_s = (track.exact_start_s - collar_s).max(window_start_ms as f64 / 1000.0);
+    track.exact_end_s = (track.exact_end_s + collar_s)
+        .min(window_end_ms as f64 / 1000.0)
+        .max(track.exact_start_s);
+    track.start_ms = start_ms;
+    track.end_ms = end_ms;
+    track.duration_ms = end_ms - start_ms;
+    track
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn relabel(pairs: &[(&str, &str)]) -> Step {
+        Step::Relabel {
+            map: pairs
+                .iter()
+                .map(|(from, to)| (from.to_string(), to.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn apply_keeps_canonical_ids() {
+        let pipeline = Pipeline {
+            steps: vec![
+                relabel(&[("edge_spk_1", "Interviewer")]),
+                Step::Merge { max_gap_ms: 500 },
+            ],
+        };
+        let tracks = pipeline.apply(
+            vec![
+                Track::test("edge_spk_1", 0, 1000),
+                Track::test("edge_spk_1", 1400, 2000),
+            ],
+            0,
+            2000,
+        );
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(tracks[0].speaker_id, "edge_spk_1");
+    }
+
+    #[test]
+    fn relabel_chains_steps_in_order() {
+        let pipeline = Pipeline {
+            steps: vec![
+                relabel(&[("edge_spk_1", "A")]),
+                relabel(&[("A", "Interviewer")]),
+            ],
+        };
+        let mut tracks = vec![
+            Track::test("edge_spk_1", 0, 1000),
+            Track::test("edge_spk_2", 1000, 2000),
+        ];
+        pipeline.relabel(&mut tracks);
+        assert_eq!(tracks[0].speaker_id, "Interviewer");
+        assert_eq!(tracks[1].speaker_id, "edge_spk_2");
+    }
+
+    #[test]
+    fn merge_gap_comes_from_the_last_merge_step() {
+        assert_eq!(Pipeline::default().merge_gap_ms(), DEFAULT_MERGE_GAP_MS);
+        let pipeline = Pipeline {
+            steps: vec![
+                Step::Merge { max_gap_ms: 100 },
+                Step::Collar { collar_ms: 50 },
+                Step::Merge { max_gap_ms: 800 },
+            ],
+        };
+        assert_eq!(pipeline.merge_gap_ms(), 800);
+        assert_eq!(Pipeline { steps: Vec::new() }.merge_gap_ms(), 0);
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Instant;
+
+use serde::Serialize;
+
+#[derive(Debug, Clone)]
+struct Span {
+    stage: &'static str,
+    start_us: u64,
+    duration_us: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProfileReport {
+    /// Total milliseconds per stage; repeated stages such as per-segment
+    /// embedding are summed.
+    pub stages_ms: BTreeMap<&'static str, f64>,
+    pub total_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trace_file: Option<String>,
+}
+
+/// Per-request stage timer. A disabled profiler records nothing, so handlers
+/// can instrument unconditionally.
+#[derive(Debug)]
+pub struct Profiler {
+    origin: Option<Instant>,
+    spans: Vec<Span>,
+}
+
+impl Profiler {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            origin: enabled.then(Instant::now),
+            spans: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, stage: &'static str, started_at: Instant) {
+        let Some(origin) = self.origin else {
+            return;
+        };
+        self.spans.push(Span {
+            stage,
+            start_us: started_at.saturating_duration_since(origin).as_micros() as u64,
+            duration_us: started_at.elapsed().as_micros() as u64,
+        });
+    }
+
+    /// Builds the report and, when `trace_dir` is set, writes the spans as a
+    /// Chrome trace (`chrome://tracing`, Perfetto) named after `label`.
+    pub fn finish(self, trace_dir: Option<&Path>, label: &str) -> Option<ProfileReport> {
+        let origin = self.origin?;
+
+        let mut stages_ms = BTreeMap::new();
+        for span in &self.spans {
+            *stages_ms.entry(span.stage).or_insert(0.0) += span.duration_us as f64 / 1000.0;
+        }
+
+        let trace_file = trace_dir.and_then(|dir| match self.write_chrome_trace(dir, label) {
+            Ok(path) => Some(path),
+            Err(error) => {
+                eprintln!("pyannote-rs sidecar failed to write profile trace: {error}");
+                None
+            }
+        });
+
+        Some(ProfileReport {
+            stages_ms,
+            total_ms: origin.elapsed().as_micros() as f64 / 1000.0,
+            trace_file,
+        })
+    }
+
+    fn write_chrome_trace(&self, dir: &Path, label: &str) -> std::io::Result<String> {
+        let events: Vec<serde_json::Value> = self
+            .spans
+            .iter()
+            .map(|span| {
+                serde_json::json!({
+                    "name": span.stage,
+                    "cat": label,
+                    "ph": "X",
+                    "ts": span.start_us,
+                    "dur": span.duration_us,
+                    "pid": std::process::id(),
+                    "tid": 1,
+                })
+            })
+            .collect();
+
+        let safe_label: String = label
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        std::fs::create_dir_all(dir)?;
+        let path = dir.join(format!(
+            "{safe_label}-{}.trace.json",
+            crate::clock::current_epoch_ms()
+        ));
+        std::fs::write(
+            &path,
+            serde_json::to_vec(&serde_json::json!({ "traceEvents": events }))?,
+        )?;
+        Ok(path.to_string_lossy().to_string())
+    }
+}
@@ -0,0 +1,108 @@
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::models::ModelSet;
+
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub name: String,
+    pub ok: bool,
+    pub detail: String,
+}
+
+impl Check {
+    fn new(name: impl Into<String>, result: Result<String, String>) -> Self {
+        let (ok, detail) = match result {
+            Ok(detail) => (true, detail),
+            Err(detail) => (false, detail),
+        };
+        Self {
+            name: name.into(),
+            ok,
+            detail,
+        }
+    }
+}
+
+fn check_model_file(path: &Path) -> Result<String, String> {
+    let file = File::open(path).map_err(|error| format!("{}: {error}", path.to_string_lossy()))?;
+    let len = file
+        .metadata()
+        .map_err(|error| format!("{}: {error}", path.to_string_lossy()))?
+        .len();
+    if len == 0 {
+        return Err(format!("{} is empty", path.to_string_lossy()));
+    }
+    Ok(format!("{} ({len} bytes)", path.to_string_lossy()))
+}
+
+fn check_dir_listable(dir: &Path) -> Result<String, String> {
+    std::fs::read_dir(dir)
+        .map(|_| dir.to_string_lossy().to_string())
+        .map_err(|error| format!("{}: {error}", dir.to_string_lossy()))
+}
+
+/// Proves writability by creating and removing a probe file, which catches
+/// read-only mounts and ACLs that metadata permission bits do not reveal.
+fn check_dir_writable(dir: &Path) -> Result<String, String> {
+    std::fs::create_dir_all(dir).map_err(|error| format!("{}: {error}", dir.to_string_lossy()))?;
+    let probe = dir.join(format!(".pyannote-rs-probe-{}", std::process::id()));
+    std::fs::write(&probe, b"probe")
+        .map_err(|error| format!("{}: {error}", dir.to_string_lossy()))?;
+    let _ = std::fs::remove_file(&probe);
+    Ok(dir.to_string_lossy().to_string())
+}
+
+fn check_free_space(dir: &Path, min_free_bytes: u64) -> Result<String, String> {
+    let available =
+        fs4::available_space(dir).map_err(|error| format!("{}: {error}", dir.to_string_lossy()))?;
+    let detail = format!(
+        "{} MiB available, {} MiB required",
+        available / (1024 * 1024),
+        min_free_bytes / (1024 * 1024)
+    );
+    if available < min_free_bytes {
+        return Err(detail);
+    }
+    Ok(detail)
+}
+
+/// Runs every readiness check and reports each one, rather than stopping at
+/// the first failure, so a single probe shows everything that is wrong.
+pub fn run_checks(
+    models: &ModelSet,
+    writable_dirs: &[(String, PathBuf)],
+    min_free_bytes: u64,
+) -> Vec<Check> {
+    let mut checks = vec![
+        Check::new(
+            "segmentation_model",
+            check_model_file(&models.segmentation_model),
+        ),
+        Check::new("embedding_model", check_model_file(&models.embedding_model)),
+    ];
+
+    let mut model_dirs: Vec<&Path> = [&models.segmentation_model, &models.embedding_model]
+        .iter()
+        .filter_map(|path| path.parent())
+        .collect();
+    model_dirs.dedup();
+    for dir in model_dirs {
+        checks.push(Check::new("model_dir", check_dir_listable(dir)));
+    }
+
+    for (name, dir) in writable_dirs {
+        checks.push(Check::new(
+            format!("{name}_writable"),
+            check_dir_writable(dir),
+        ));
+        checks.push(Check::new(
+            format!("{name}_free_space"),
+            check_free_space(dir, min_free_bytes),
+        ));
+    }
+
+    checks
+}
@@ -0,0 +1,369 @@
+use serde::Serialize;
+
+use crate::speakers::SpeakerRegistry;
+use crate::state::SessionState;
+use crate::timeline::StoredEmbedding;
+use crate::track::{merge_adjacent_tracks, Track};
+use crate::{assignment, clustering, timeline};
+
+const PENDING: &str = "\0";
+
+/// One span whose speaker changed.
+#[derive(Debug, Clone, Serialize)]
+pub struct LabelChange {
+    pub start_ms: i64,
+    pub end_ms: i64,
+    pub from_speaker_id: String,
+    pub to_speaker_id: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Recluster {
+    pub speaker_count: usize,
+    pub segment_count: usize,
+    pub segments_relabeled: usize,
+    pub speakers_added: Vec<String>,
+    pub speakers_removed: Vec<String>,
+    /// Relabeled spans in time order. Spans with the same change are joined
+    /// across gaps the timeline would merge anyway. Everything not listed
+    /// kept its speaker.
+    pub changes: Vec<LabelChange>,
+}
+
+/// What [`apply`] needs from a session, taken so clustering can run off the
+/// session worker.
+#[derive(Debug)]
+pub struct Input {
+    pub embeddings: Vec<Vec<f32>>,
+    spans: Vec<(i64, i64)>,
+}
+
+pub fn input(session: &SessionState) -> Input {
+    Input {
+        embeddings: session
+            .embeddings
+            .iter()
+            .map(|stored| stored.embedding.clone())
+            .collect(),
+        spans: spans(session),
+    }
+}
+
+fn spans(session: &SessionState) -> Vec<(i64, i64)> {
+    session
+        .embeddings
+        .iter()
+        .map(|stored| (stored.start_ms, stored.end_ms))
+        .collect()
+}
+
+/// Whether the session's segments are still the ones `input` was taken from.
+pub fn is_current(session: &SessionState, input: &Input) -> bool {
+    spans(session) == input.spans
+}
+
+/// Applies a global re-clustering of the session's stored segment embeddings
+/// (`labels`, one per embedding in `input`) with as little label churn as
+/// possible: new clusters are matched to existing speakers by a
+/// maximum-overlap assignment over speech duration, so a speaker keeps their
+/// id when most of their speech stays together. Only unmatched clusters get
+/// new ids, and only speakers left without a cluster disappear.
+pub fn apply(session: &mut SessionState, input: &Input, labels: &[usize]) -> Recluster {
+    let merge_gap_ms = session.config.postprocess.merge_gap_ms();
+    apply_to(
+        &mut session.manager,
+        &mut session.embeddings,
+        &mut session.timeline,
+        merge_gap_ms,
+        input,
+        labels,
+    )
+}
+
+/// [`apply`] on the parts of a session it rewrites: the speaker pool, the
+/// stored segments and the timeline.
+fn apply_to(
+    manager: &mut SpeakerRegistry,
+    segments: &mut [StoredEmbedding],
+    tracks: &mut Vec<Track>,
+    merge_gap_ms: i64,
+    input: &Input,
+    labels: &[usize],
+) -> Recluster {
+    let embeddings = &input.embeddings;
+    let cluster_count = labels.iter().copied().max().unwrap_or(0);
+
+    let mut old_ids: Vec<usize> = segments.iter().map(|stored| stored.speaker_id).collect();
+    old_ids.sort_unstable();
+    old_ids.dedup();
+
+    let mut overlap = vec![vec![0i64; old_ids.len()]; cluster_count];
+    for (stored, &label) in segments.iter().zip(labels) {
+        let Ok(old) = old_ids.binary_search(&stored.speaker_id) else {
+            continue;
+        };
+        overlap[label - 1][old] += (stored.end_ms - stored.start_ms).max(1);
+    }
+    let matching = assignment::maximize(&overlap);
+
+    let mut speakers_added = Vec::new();
+    let mut speaker_of_cluster = Vec::with_capacity(cluster_count);
+    for (cluster, matched) in matching.iter().enumerate() {
+        let members: Vec<&[f32]> = labels
+            .iter()
+            .zip(embeddings)
+            .filter(|(label, _)| **label == cluster + 1)
+            .map(|(_, embedding)| embedding.as_slice())
+            .collect();
+        let centroid = clustering::centroid(&members);
+        let speaker_id = match matched {
+            Some(old) => {
+                manager.set_speaker(old_ids[*old], centroid);
+                old_ids[*old]
+            }
+            None => {
+                let speaker_id = manager.add_speaker(centroid);
+                speakers_added.push(format!("edge_spk_{speaker_id}"));
+                speaker_id
+            }
+        };
+        speaker_of_cluster.push(speaker_id);
+    }
+
+    let mut speakers_removed = Vec::new();
+    for (old, speaker_id) in old_ids.iter().enumerate() {
+        if !matching.contains(&Some(old)) && manager.remove_speaker(*speaker_id) {
+            speakers_removed.push(format!("edge_spk_{speaker_id}"));
+        }
+    }
+
+    let mut changes: Vec<LabelChange> = Vec::new();
+    let mut segments_relabeled = 0;
+    let mut order: Vec<usize> = (0..segments.len()).collect();
+    order.sort_by_key(|&index| (segments[index].start_ms, segments[index].end_ms));
+    for index in order {
+        let stored = &mut segments[index];
+        let speaker_id = speaker_of_cluster[labels[index] - 1];
+        if speaker_id == stored.speaker_id {
+            continue;
+        }
+        segments_relabeled += 1;
+        let change = LabelChange {
+            start_ms: stored.start_ms,
+            end_ms: stored.end_ms,
+            from_speaker_id: format!("edge_spk_{}", stored.speaker_id),
+            to_speaker_id: format!("edge_spk_{speaker_id}"),
+        };
+        stored.speaker_id = speaker_id;
+        match changes.last_mut() {
+            Some(last)
+                if last.from_speaker_id == change.from_speaker_id
+                    && last.to_speaker_id == change.to_speaker_id
+                    && change.start_ms <= last.end_ms + merge_gap_ms =>
+            {
+                last.end_ms = last.end_ms.max(change.end_ms);
+            }
+            _ => changes.push(change),
+        }
+    }
+
+    // Relabeled tracks are parked under a marked id until every change has
+    // been applied, so a later change cannot pick them up a second time.
+    let mut relabeled = std::mem::take(tracks);
+    for change in &changes {
+        timeline::relabel_range(
+            &mut relabeled,
+            change.start_ms,
+            change.end_ms,
+            &change.from_speaker_id,
+            &format!("{PENDING}{}", change.to_speaker_id),
+        );
+    }
+    for track in &mut relabeled {
+        if let Some(speaker_id) = track.speaker_id.strip_prefix(PENDING) {
+            track.speaker_id = speaker_id.to_string();
+        }
+    }
+    *tracks = merge_adjacent_tracks(relabeled, merge_gap_ms);
+
+    Recluster {
+        speaker_count: cluster_count,
+        segment_count: labels.len(),
+        segments_relabeled,
+        speakers_added,
+        speakers_removed,
+        changes,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fixture {
+        manager: SpeakerRegistry,
+        segments: Vec<StoredEmbedding>,
+        tracks: Vec<Track>,
+    }
+
+    impl Fixture {
+        /// Enrolls speakers 1..=`speakers` and stores one segment per
+        /// `(speaker_id, start_ms, end_ms, embedding)`.
+        fn new(speakers: usize, segments: &[(usize, i64, i64, [f32; 2])]) -> Self {
+            let mut manager = SpeakerRegistry::new(8);
+            for _ in 0..speakers {
+                manager.add_speaker(vec![0.0, 0.0]);
+            }
+            Self {
+                manager,
+                segments: segments
+                    .iter()
+                    .map(
+                        |&(speaker_id, start_ms, end_ms, embedding)| StoredEmbedding {
+                            speaker_id,
+                            start_ms,
+                            end_ms,
+                            embedding: embedding.to_vec(),
+                        },
+                    )
+                    .collect(),
+                tracks: Vec::new(),
+            }
+        }
+
+        fn apply(&mut self, labels: &[usize]) -> Recluster {
+            let input = Input {
+                embeddings: self
+                    .segments
+                    .iter()
+                    .map(|stored| stored.embedding.clone())
+                    .collect(),
+                spans: Vec::new(),
+            };
+            apply_to(
+                &mut self.manager,
+                &mut self.segments,
+                &mut self.tracks,
+                0,
+                &input,
+                labels,
+            )
+        }
+
+        fn timeline(&self) -> Vec<(&str, i64, i64)> {
+            self.tracks
+                .iter()
+                .map(|track| (track.speaker_id.as_str(), track.start_ms, track.end_ms))
+                .collect()
+        }
+    }
+
+    #[test]
+    fn existing_ids_survive_and_new_clusters_get_new_ids() {
+        let mut fixture = Fixture::new(
+            2,
+            &[
+                (1, 0, 1000, [1.0, 0.0]),
+                (1, 1000, 2000, [1.0, 0.0]),
+                (2, 2000, 3000, [0.0, 1.0]),
+                (1, 3000, 4000, [0.0, 1.0]),
+                (2, 4000, 5000, [-1.0, 0.0]),
+            ],
+        );
+        fixture.tracks = vec![
+            Track::test("edge_spk_1", 0, 2000),
+            Track::test("edge_spk_2", 2000, 3000),
+            Track::test("edge_spk_1", 3000, 4000),
+            Track::test("edge_spk_2", 4000, 5000),
+        ];
+
+        let result = fixture.apply(&[1, 1, 2, 2, 3]);
+        assert_eq!(result.speaker_count, 3);
+        assert_eq!(result.segments_relabeled, 2);
+        assert_eq!(result.speakers_added, ["edge_spk_3"]);
+        assert!(result.speakers_removed.is_empty());
+        let changes: Vec<_> = result
+            .changes
+            .iter()
+            .map(|change| {
+                (
+                    change.start_ms,
+                    change.from_speaker_id.as_str(),
+                    change.to_speaker_id.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                (3000, "edge_spk_1", "edge_spk_2"),
+                (4000, "edge_spk_2", "edge_spk_3"),
+            ]
+        );
+
+        assert_eq!(
+            fixture.timeline(),
+            [
+                ("edge_spk_1", 0, 2000),
+                ("edge_spk_2", 2000, 4000),
+                ("edge_spk_3", 4000, 5000),
+            ]
+        );
+        let speaker_ids: Vec<usize> = fixture.segments.iter().map(|s| s.speaker_id).collect();
+        assert_eq!(speaker_ids, [1, 1, 2, 2, 3]);
+        assert_eq!(fixture.manager.voiceprint(1), Some(&[1.0, 0.0][..]));
+        assert_eq!(fixture.manager.voiceprint(3), Some(&[-1.0, 0.0][..]));
+    }
+
+    #[test]
+    fn relabeled_tracks_are_not_relabeled_again() {
+        // Speaker 1's segment moves to speaker 2 while an overlapping
+        // speaker 2 segment moves to a new speaker; the track that just
+        // became speaker 2 must not follow it.
+        let mut fixture = Fixture::new(
+            2,
+            &[
+                (1, 0, 1000, [1.0, 0.0]),
+                (2, 500, 2000, [0.0, 1.0]),
+                (2, 3000, 6000, [1.0, 0.0]),
+            ],
+        );
+        fixture.tracks = vec![
+            Track::test("edge_spk_1", 0, 1000),
+            Track::test("edge_spk_2", 500, 2000),
+            Track::test("edge_spk_2", 3000, 6000),
+        ];
+
+        let result = fixture.apply(&[1, 2, 1]);
+        assert_eq!(result.speakers_added, ["edge_spk_3"]);
+        assert_eq!(result.speakers_removed, ["edge_spk_1"]);
+        assert_eq!(
+            fixture.timeline(),
+            [
+                ("edge_spk_2", 0, 1000),
+                ("edge_spk_3", 500, 2000),
+                ("edge_spk_2", 3000, 6000),
+            ]
+        );
+        assert!(fixture.manager.voiceprint(1).is_none());
+        assert!(fixture
+            .tracks
+            .iter()
+            .all(|track| !track.speaker_id.starts_with(PENDING)));
+    }
+
+    #[test]
+    fn an_unchanged_clustering_changes_nothing() {
+        let mut fixture = Fixture::new(2, &[(1, 0, 1000, [1.0, 0.0]), (2, 1000, 2000, [0.0, 1.0])]);
+        fixture.tracks = vec![
+            Track::test("edge_spk_1", 0, 1000),
+            Track::test("edge_spk_2", 1000, 2000),
+        ];
+
+        let result = fixture.apply(&[2, 1]);
+        assert_eq!(result.segments_relabeled, 0);
+        assert!(result.changes.is_empty());
+        assert!(result.speakers_added.is_empty() && result.speakers_removed.is_empty());
+        assert_eq!(fixture.manager.next_speaker_id(), 3);
+    }
+}
@@ -0,0 +1,203 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::analytics::AnalyticsResponse;
+use crate::snapshot::ConfigSnapshot;
+use crate::track::Track;
+
+#[derive(Debug, Serialize)]
+pub struct TimelineReport<'a> {
+    pub recording_id: &'a str,
+    pub sample_rate: u32,
+    pub duration_ms: i64,
+    pub speaker_count: usize,
+    pub tracks: &'a [Track],
+    pub warnings: &'a [String],
+    pub config: &'a ConfigSnapshot,
+}
+
+/// An analytics result together with the configuration that produced the
+/// timeline it was computed from.
+#[derive(Debug, Serialize)]
+pub struct AnalyticsExport<T> {
+    #[serde(flatten)]
+    pub analytics: T,
+    pub config: ConfigSnapshot,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SpeakerStats {
+    pub speaker_id: String,
+    pub track_count: usize,
+    pub speech_ms: i64,
+    /// Fraction of all attributed speech, in [0, 1].
+    pub speech_share: f64,
+    pub first_start_ms: i64,
+    pub last_end_ms: i64,
+}
+
+/// Writes one interview's report bundle into `dir`, creating it if needed:
+/// `timeline.json`, `diarization.rttm`, `analytics.json` and `speakers.csv`.
+/// Returns the paths written.
+pub fn write_bundle(
+    dir: &Path,
+    timeline: &TimelineReport<'_>,
+    analytics: &AnalyticsExport<AnalyticsResponse>,
+) -> io::Result<Vec<PathBuf>> {
+    fs::create_dir_all(dir)?;
+
+    let files = [
+        ("timeline.json", to_json(timeline)?),
+        (
+            "diarization.rttm",
+            rttm(timeline.recording_id, timeline.tracks),
+        ),
+        ("analytics.json", to_json(analytics)?),
+        (
+            "speakers.csv",
+            speakers_csv(&speaker_stats(timeline.tracks)),
+        ),
+    ];
+
+    let mut written = Vec::with_capacity(files.len());
+    for (name, contents) in files {
+        let path = dir.join(name);
+        fs::write(&path, contents)?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+fn to_json(value: &impl Serialize) -> io::Result<String> {
+    serde_json::to_string_pretty(value).map_err(io::Error::other)
+}
+
+/// NIST RTTM, one `SPEAKER` line per track with times in seconds.
+fn rttm(recording_id: &str, tracks: &[Track]) -> String {
+    // RTTM fields are space separated, so neither the file id nor a relabeled
+    // speaker name can contain spaces.
+    let file_id = recording_id.replace(char::is_whitespace, "_");
+    tracks
+        .iter()
+        .map(|track| {
+            format!(
+                "SPEAKER {file_id} 1 {:.3} {:.3} <NA> <NA> {} <NA> <NA>\n",
+                track.start_ms as f64 / 1000.0,
+                track.duration_ms as f64 / 1000.0,
+                track.speaker_id.replace(char::is_whitespace, "_")
+            )
+        })
+        .collect()
+}
+
+/// Per-speaker totals over `tracks`, ordered by speaker id.
+pub fn speaker_stats(tracks: &[Track]) -> Vec<SpeakerStats> {
+    let mut stats: BTreeMap<&str, SpeakerStats> = BTreeMap::new();
+    for track in tracks {
+        let entry = stats
+            .entry(&track.speaker_id)
+            .or_insert_with(|| SpeakerStats {
+                speaker_id: track.speaker_id.clone(),
+                track_count: 0,
+                speech_ms: 0,
+                speech_share: 0.0,
+                first_start_ms: track.start_ms,
+                last_end_ms: track.end_ms,
+            });
+        entry.track_count += 1;
+        entry.speech_ms += track.duration_ms;
+        entry.first_start_ms = entry.first_start_ms.min(track.start_ms);
+        entry.last_end_ms = entry.last_end_ms.max(track.end_ms);
+    }
+
+    let total_speech_ms: i64 = stats.values().map(|item| item.speech_ms).sum();
+    stats
+        .into_values()
+        .map(|mut item| {
+            if total_speech_ms > 0 {
+                item.speech_share = item.speech_ms as f64 / total_speech_ms as f64;
+            }
+            item
+        })
+        .collect()
+}
+
+/// Track CSV. The columns are stable; new ones are only ever appended:
+/// `speaker_id,start_ms,end_ms,duration_ms,local_start_ms,local_end_ms`.
+pub fn tracks_csv(tracks: &[Track]) -> String {
+    let mut csv =
+        String::from("speaker_id,start_ms,end_ms,duration_ms,local_start_ms,local_end_ms\n");
+    for track in tracks {
+        csv.push_str(&format!(
+            "{},{},{},{},{},{}\n",
+            csv_field(&track.speaker_id),
+            track.start_ms,
+            track.end_ms,
+            track.duration_ms,
+            track.local_start_ms,
+            track.local_end_ms
+        ));
+    }
+    csv
+}
+
+/// Per-speaker CSV. The columns are stable; new ones are only ever appended:
+/// `speaker_id,track_count,speech_ms,speech_share,first_start_ms,last_end_ms`.
+/// `speech_share` has four decimals.
+pub fn speakers_csv(stats: &[SpeakerStats]) -> String {
+    let mut csv =
+        String::from("speaker_id,track_count,speech_ms,speech_share,first_start_ms,last_end_ms\n");
+    for item in stats {
+        csv.push_str(&format!(
+            "{},{},{},{:.4},{},{}\n",
+            csv_field(&item.speaker_id),
+            item.track_count,
+            item.speech_ms,
+            item.speech_share,
+            item.first_start_ms,
+            item.last_end_ms
+        ));
+    }
+    csv
+}
+
+/// Quotes a field when it holds a comma, quote or line break, e.g. a model
+/// path or a relabeled speaker name.
+pub fn csv_field(raw: &str) -> String {
+    if raw.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", raw.replace('"', "\"\""))
+    } else {
+        raw.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_quotes_relabeled_speaker_names() {
+        let tracks = [Track::test("Smith, \"Jo\"", 0, 1500)];
+        assert_eq!(
+            tracks_csv(&tracks).lines().nth(1),
+            Some("\"Smith, \"\"Jo\"\"\",0,1500,1500,0,1500")
+        );
+        assert_eq!(
+            speakers_csv(&speaker_stats(&tracks)).lines().nth(1),
+            Some("\"Smith, \"\"Jo\"\"\",1,1500,1.0000,0,1500")
+        );
+    }
+
+    #[test]
+    fn rttm_keeps_one_token_per_field() {
+        let rttm = rttm("mock interview", &[Track::test("Jo Smith", 250, 1500)]);
+        assert_eq!(
+            rttm,
+            "SPEAKER mock_interview 1 0.250 1.250 <NA> <NA> Jo_Smith <NA> <NA>\n"
+        );
+    }
+}
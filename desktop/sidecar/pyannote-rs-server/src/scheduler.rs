@@ -0,0 +1,220 @@
+use std::collections::BTreeSet;
+use std::pin::pin;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+
+/// Priority class of offline work. Live session windows outrank both: they
+/// never queue here, and offline work pauses between segments while any live
+/// window is being diarized.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    /// A recording the user is waiting on.
+    #[default]
+    Offline,
+    /// Bulk reprocessing; only runs when no offline work is waiting.
+    Backfill,
+}
+
+#[derive(Debug, Default)]
+struct Slots {
+    running: usize,
+    waiting: BTreeSet<(Priority, u64)>,
+}
+
+/// Hands out a fixed number of offline worker slots in priority order, first
+/// come first served within a class.
+#[derive(Debug)]
+pub struct Scheduler {
+    workers: usize,
+    next_ticket: AtomicU64,
+    slots: Mutex<Slots>,
+    slots_changed: Notify,
+    live_windows: AtomicUsize,
+    live_idle: Notify,
+}
+
+/// An occupied offline worker slot, released on drop.
+#[derive(Debug)]
+pub struct WorkerSlot {
+    scheduler: Arc<Scheduler>,
+}
+
+/// A ticket in the wait queue. Dropping it before it is granted, e.g. when
+/// the request awaiting a slot is cancelled, takes it out of the queue again
+/// so it cannot block the waiters behind it.
+struct Ticket<'a> {
+    scheduler: &'a Scheduler,
+    ticket: (Priority, u64),
+    granted: bool,
+}
+
+impl Drop for Ticket<'_> {
+    fn drop(&mut self) {
+        if self.granted {
+            return;
+        }
+        self.scheduler
+            .slots
+            .lock()
+            .unwrap()
+            .waiting
+            .remove(&self.ticket);
+        self.scheduler.slots_changed.notify_waiters();
+    }
+}
+
+/// Marks a live window as in flight for as long as it is held.
+#[derive(Debug)]
+pub struct LiveWindow<'a> {
+    scheduler: &'a Scheduler,
+}
+
+impl Scheduler {
+    pub fn new(workers: usize) -> Self {
+        Self {
+            workers: workers.max(1),
+            next_ticket: AtomicU64::new(0),
+            slots: Mutex::new(Slots::default()),
+            slots_changed: Notify::new(),
+            live_windows: AtomicUsize::new(0),
+            live_idle: Notify::new(),
+        }
+    }
+
+    pub async fn acquire(self: &Arc<Self>, priority: Priority) -> WorkerSlot {
+        let ticket = (priority, self.next_ticket.fetch_add(1, Ordering::Relaxed));
+        self.slots.lock().unwrap().waiting.insert(ticket);
+        let mut pending = Ticket {
+            scheduler: self,
+            ticket,
+            granted: false,
+        };
+        loop {
+            // Registered before checking, so a release in between still wakes us.
+            let mut changed = pin!(self.slots_changed.notified());
+            changed.as_mut().enable();
+            {
+                let mut slots = self.slots.lock().unwrap();
+                if slots.running < self.workers && slots.waiting.first() == Some(&ticket) {
+                    slots.waiting.remove(&ticket);
+                    slots.running += 1;
+                    pending.granted = true;
+                    // The next waiter may fit into a remaining slot.
+                    self.slots_changed.notify_waiters();
+                    return WorkerSlot {
+                        scheduler: self.clone(),
+                    };
+                }
+            }
+            changed.await;
+        }
+    }
+
+    pub fn live_window(&self) -> LiveWindow<'_> {
+        self.live_windows.fetch_add(1, Ordering::SeqCst);
+        LiveWindow { scheduler: self }
+    }
+
+    /// Waits until no live window is in flight.
+    pub async fn yield_to_live(&self) {
+        loop {
+            let mut idle = pin!(self.live_idle.notified());
+            idle.as_mut().enable();
+            if self.live_windows.load(Ordering::SeqCst) == 0 {
+                return;
+            }
+            idle.await;
+        }
+    }
+}
+
+impl Drop for WorkerSlot {
+    fn drop(&mut self) {
+        self.scheduler.slots.lock().unwrap().running -= 1;
+        self.scheduler.slots_changed.notify_waiters();
+    }
+}
+
+impl Drop for LiveWindow<'_> {
+    fn drop(&mut self) {
+        if self.scheduler.live_windows.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.scheduler.live_idle.notify_waiters();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    async fn acquire_soon(scheduler: &Arc<Scheduler>, priority: Priority) -> Option<WorkerSlot> {
+        tokio::time::timeout(Duration::from_millis(200), scheduler.acquire(priority))
+            .await
+            .ok()
+    }
+
+    #[tokio::test]
+    async fn dropped_acquire_leaves_the_queue() {
+        let scheduler = Arc::new(Scheduler::new(1));
+        let held = scheduler.acquire(Priority::Offline).await;
+
+        // Times out while the only slot is held, dropping the pending acquire.
+        assert!(acquire_soon(&scheduler, Priority::Offline).await.is_none());
+        assert!(scheduler.slots.lock().unwrap().waiting.is_empty());
+
+        drop(held);
+        assert!(acquire_soon(&scheduler, Priority::Offline).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn offline_waiters_go_before_backfill() {
+        let scheduler = Arc::new(Scheduler::new(1));
+        let held = scheduler.acquire(Priority::Offline).await;
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let mut tasks = Vec::new();
+        for priority in [
+            Priority::Backfill,
+            Priority::Offline,
+            Priority::Backfill,
+            Priority::Offline,
+        ] {
+            let scheduler = scheduler.clone();
+            let order = order.clone();
+            tasks.push(tokio::spawn(async move {
+                let _slot = scheduler.acquire(priority).await;
+                order.lock().unwrap().push(priority);
+            }));
+            // Lets the task enqueue its ticket before the next one.
+            tokio::task::yield_now().await;
+        }
+
+        drop(held);
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(
+            *order.lock().unwrap(),
+            [
+                Priority::Offline,
+                Priority::Offline,
+                Priority::Backfill,
+                Priority::Backfill
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn slots_limit_concurrency() {
+        let scheduler = Arc::new(Scheduler::new(2));
+        let _first = scheduler.acquire(Priority::Offline).await;
+        let _second = scheduler.acquire(Priority::Backfill).await;
+        assert!(acquire_soon(&scheduler, Priority::Offline).await.is_none());
+    }
+}
@@ -0,0 +1,182 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    Cosine,
+    Plda,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Normalization {
+    #[default]
+    None,
+    Length,
+}
+
+/// Two-covariance PLDA backend in its diagonalized form: embeddings are
+/// centered with `mean` and projected with `transform`, after which the
+/// within-speaker covariance is identity and the across-speaker covariance is
+/// `diag(psi)`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PldaBackend {
+    pub mean: Vec<f32>,
+    pub transform: Vec<Vec<f32>>,
+    pub psi: Vec<f32>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ScoringFile {
+    metric: Metric,
+    #[serde(default)]
+    normalization: Normalization,
+    plda: Option<PldaBackend>,
+}
+
+/// Pairwise speaker similarity. Every metric reports on a scale where the
+/// session threshold keeps its meaning: cosine similarity as-is, PLDA as the
+/// same-speaker posterior `sigmoid(llr)` under equal priors.
+#[derive(Debug, Clone)]
+pub struct Scorer {
+    metric: Metric,
+    normalization: Normalization,
+    plda: Option<PldaBackend>,
+}
+
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a * norm_b)
+}
+
+fn length_normalize(values: &mut [f32], target_norm: f32) {
+    let norm = values.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        values
+            .iter_mut()
+            .for_each(|value| *value *= target_norm / norm);
+    }
+}
+
+impl Scorer {
+    pub fn cosine() -> Self {
+        Self {
+            metric: Metric::Cosine,
+            normalization: Normalization::None,
+            plda: None,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let raw = std::fs::read_to_string(path).map_err(|error| {
+            format!(
+                "cannot read scoring config {}: {error}",
+                path.to_string_lossy()
+            )
+        })?;
+        let file: ScoringFile = serde_json::from_str(&raw).map_err(|error| {
+            format!("invalid scoring config {}: {error}", path.to_string_lossy())
+        })?;
+
+        if let Some(plda) = &file.plda {
+            if plda.mean.is_empty() || plda.transform.is_empty() {
+                return Err("plda backend requires non-empty mean and transform".to_string());
+            }
+            if plda
+                .transform
+                .iter()
+                .any(|row| row.len() != plda.mean.len())
+            {
+                return Err("plda transform rows must match the mean dimension".to_string());
+            }
+            if plda.psi.len() != plda.transform.len() {
+                return Err("plda psi must have one entry per transform row".to_string());
+            }
+            if plda
+                .psi
+                .iter()
+                .any(|value| !value.is_finite() || *value < 0.0)
+            {
+                return Err("plda psi entries must be finite and non-negative".to_string());
+            }
+        }
+        if file.metric == Metric::Plda && file.plda.is_none() {
+            return Err("metric plda requires a plda backend section".to_string());
+        }
+
+        Ok(Self {
+            metric: file.metric,
+            normalization: file.normalization,
+            plda: file.plda,
+        })
+    }
+
+    pub fn metric(&self) -> Metric {
+        self.metric
+    }
+
+    pub fn normalization(&self) -> Normalization {
+        self.normalization
+    }
+
+    /// Embedding dimension the backend was trained for, if it constrains one.
+    pub fn expected_dim(&self) -> Option<usize> {
+        match self.metric {
+            Metric::Cosine => None,
+            Metric::Plda => self.plda.as_ref().map(|plda| plda.mean.len()),
+        }
+    }
+
+    pub fn score(&self, a: &[f32], b: &[f32]) -> f32 {
+        match (self.metric, self.plda.as_ref()) {
+            (Metric::Plda, Some(plda)) => {
+                let u = self.project(plda, a);
+                let v = self.project(plda, b);
+                let llr = plda_llr(&plda.psi, &u, &v);
+                1.0 / (1.0 + (-llr).exp())
+            }
+            _ => cosine_similarity(a, b),
+        }
+    }
+
+    fn project(&self, plda: &PldaBackend, embedding: &[f32]) -> Vec<f32> {
+        let centered: Vec<f32> = embedding
+            .iter()
+            .zip(&plda.mean)
+            .map(|(x, m)| x - m)
+            .collect();
+        let mut projected: Vec<f32> = plda
+            .transform
+            .iter()
+            .map(|row| row.iter().zip(&centered).map(|(w, x)| w * x).sum())
+            .collect();
+        if self.normalization == Normalization::Length {
+            let target_norm = (projected.len() as f32).sqrt();
+            length_normalize(&mut projected, target_norm);
+        }
+        projected
+    }
+}
+
+/// Same-speaker vs different-speaker log-likelihood ratio for one enrollment
+/// vector against one test vector, summed over the independent dimensions.
+fn plda_llr(psi: &[f32], u: &[f32], v: &[f32]) -> f32 {
+    psi.iter()
+        .zip(u.iter().zip(v))
+        .map(|(&psi, (&u, &v))| {
+            let total = psi + 1.0;
+            let joint_det = total * total - psi * psi;
+            let joint =
+                (total * u * u - 2.0 * psi * u * v + total * v * v) / joint_det + joint_det.ln();
+            let marginal = (u * u + v * v) / total + 2.0 * total.ln();
+            0.5 * (marginal - joint)
+        })
+        .sum()
+}
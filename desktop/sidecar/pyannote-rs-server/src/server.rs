@@ -0,0 +1,298 @@
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::task::Poll;
+use std::time::Duration;
+
+use axum::extract::{ConnectInfo, Request};
+use axum::Router;
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto::Builder;
+use hyper_util::service::TowerToHyperService;
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+use tower::ServiceExt;
+
+use crate::admission::ConnectionLimiter;
+
+/// How long an HTTP/2 keep-alive ping may go unanswered before the
+/// connection is closed.
+const HTTP2_PING_TIMEOUT: Duration = Duration::from_secs(20);
+
+#[derive(Debug, Clone)]
+pub struct HttpTuning {
+    pub http2: bool,
+    pub http1_keep_alive: bool,
+    /// HTTP/1.1 only, see [`serve`].
+    pub idle_timeout: Option<Duration>,
+    pub http2_max_concurrent_streams: u32,
+    pub http2_keep_alive_interval: Option<Duration>,
+}
+
+/// Binds one listener per address `hosts` resolve to, skipping duplicates.
+/// `localhost` always means both `127.0.0.1` and `::1`, since some systems
+/// resolve it to only one of them and clients may pick the other; on hosts
+/// without IPv6 the `::1` half is skipped with a warning. The IPv6
+/// wildcard `::` is bound dual-stack and also accepts IPv4; any other IPv6
+/// address is bound IPv6-only, so it can sit next to its IPv4 counterpart.
+/// With port 0 the first listener's ephemeral port is reused for the rest.
+pub async fn bind(hosts: &[String], port: u16) -> io::Result<Vec<TcpListener>> {
+    // Each address with whether it was only implied by `localhost`.
+    let mut addrs: Vec<(SocketAddr, bool)> = Vec::new();
+    for host in hosts {
+        let resolved: Vec<(SocketAddr, bool)> = if host.eq_ignore_ascii_case("localhost") {
+            vec![
+                (
+                    SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port),
+                    false,
+                ),
+                (SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), port), true),
+            ]
+        } else if let Ok(ip) = host.trim_matches(['[', ']']).parse::<IpAddr>() {
+            vec![(SocketAddr::new(ip, port), false)]
+        } else {
+            tokio::net::lookup_host((host.as_str(), port))
+                .await?
+                .map(|addr| (addr, false))
+                .collect()
+        };
+        for (addr, implied) in resolved {
+            match addrs.iter_mut().find(|(known, _)| *known == addr) {
+                // Named explicitly elsewhere, so it must bind after all.
+                Some((_, known_implied)) => *known_implied &= implied,
+                None => addrs.push((addr, implied)),
+            }
+        }
+    }
+
+    let mut listeners = Vec::with_capacity(addrs.len());
+    let mut port = port;
+    for (mut addr, implied) in addrs {
+        addr.set_port(port);
+        let listener = match bind_one(addr) {
+            Ok(listener) => listener,
+            Err(error) if implied && ipv6_unavailable(&error) => {
+                // `127.0.0.1` is never implied, so something still binds.
+                eprintln!("pyannote-rs sidecar skipping {addr} for localhost: {error}");
+                continue;
+            }
+            Err(error) => {
+                return Err(io::Error::new(
+                    error.kind(),
+                    format!("failed to bind {addr}: {error}"),
+                ))
+            }
+        };
+        port = listener.local_addr()?.port();
+        listeners.push(listener);
+    }
+    Ok(listeners)
+}
+
+/// `EAFNOSUPPORT`, which `io::ErrorKind` has no variant for.
+#[cfg(target_os = "linux")]
+const EAFNOSUPPORT: i32 = 97;
+#[cfg(windows)]
+const EAFNOSUPPORT: i32 = 10047;
+#[cfg(not(any(target_os = "linux", windows)))]
+const EAFNOSUPPORT: i32 = 47;
+
+/// Whether binding failed because the host has no IPv6 loopback, as on
+/// systems with IPv6 disabled.
+fn ipv6_unavailable(error: &io::Error) -> bool {
+    error.kind() == io::ErrorKind::AddrNotAvailable || error.raw_os_error() == Some(EAFNOSUPPORT)
+}
+
+fn bind_one(addr: SocketAddr) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(!addr.ip().is_unspecified())?;
+    }
+    // Matches `TcpListener::bind`, so a restarted sidecar can rebind at once.
+    #[cfg(not(windows))]
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    TcpListener::from_std(socket.into())
+}
+
+/// Accept loop serving HTTP/1.1 and, unless disabled, cleartext HTTP/2 with
+/// prior knowledge on the same port. Unlike `axum::serve` this exposes the
+/// keep-alive and idle settings of the underlying hyper connections, and
+/// accepts on several listeners at once.
+///
+/// On HTTP/1.1 the idle timeout is hyper's header read timeout, which also
+/// runs while a keep-alive connection waits for its next request. HTTP/2 has
+/// no idle timeout: a multiplexed connection is expected to stay open, and a
+/// peer that went away is detected by its unanswered keep-alive ping.
+///
+/// Connections over `limiter`'s limits for their remote address are closed
+/// right after accept, before any request is read.
+pub async fn serve(
+    listeners: Vec<TcpListener>,
+    app: Router,
+    tuning: HttpTuning,
+    limiter: ConnectionLimiter,
+    shutdown: impl std::future::Future<Output = ()>,
+) {
+    let mut builder = Builder::new(TokioExecutor::new());
+    builder
+        .http1()
+        .timer(TokioTimer::new())
+        .keep_alive(tuning.http1_keep_alive)
+        .header_read_timeout(tuning.idle_timeout);
+    builder
+        .http2()
+        .timer(TokioTimer::new())
+        .max_concurrent_streams(tuning.http2_max_concurrent_streams)
+        .keep_alive_interval(tuning.http2_keep_alive_interval)
+        .keep_alive_timeout(HTTP2_PING_TIMEOUT);
+    if !tuning.http2 {
+        builder = builder.http1_only();
+    }
+
+    let (shutdown_tx, shutdown_rx) = watch::channel(());
+    let mut connections = JoinSet::new();
+    let mut prune = tokio::time::interval(Duration::from_secs(60));
+    tokio::pin!(shutdown);
+    let mut next_listener = 0;
+
+    loop {
+        let (stream, remote_addr) = tokio::select! {
+            accepted = accept(&listeners, &mut next_listener) => match accepted {
+                Ok(accepted) => accepted,
+                Err(error) => {
+                    // Usually descriptor exhaustion; back off instead of spinning.
+                    eprintln!("pyannote-rs sidecar accept failed: {error}");
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    continue;
+                }
+            },
+            _ = prune.tick() => {
+                limiter.prune();
+                continue;
+            }
+            _ = &mut shutdown => break,
+        };
+
+        let admission = match limiter.admit(remote_addr.ip()) {
+            Ok(admission) => admission,
+            Err((rejection, first)) => {
+                if first {
+                    eprintln!(
+                        "pyannote-rs sidecar refusing connections from {}: {rejection}",
+                        remote_addr.ip()
+                    );
+                }
+                continue;
+            }
+        };
+
+        // Requests are small and latency-bound, so never wait to coalesce.
+        let _ = stream.set_nodelay(true);
+
+        // Same extension `into_make_service_with_connect_info` would insert, so
+        // handlers and middleware can use the `ConnectInfo` extractor.
+        let service = app.clone().map_request(move |mut request: Request<_>| {
+            request.extensions_mut().insert(ConnectInfo(remote_addr));
+            request
+        });
+        let connection = builder
+            .serve_connection_with_upgrades(TokioIo::new(stream), TowerToHyperService::new(service))
+            .into_owned();
+        let mut shutdown_rx = shutdown_rx.clone();
+        connections.spawn(async move {
+            let _admission = admission;
+            tokio::pin!(connection);
+            tokio::select! {
+                _ = connection.as_mut() => {}
+                _ = shutdown_rx.changed() => {
+                    connection.as_mut().graceful_shutdown();
+                    let _ = connection.await;
+                }
+            }
+        });
+
+        while connections.try_join_next().is_some() {}
+    }
+
+    let _ = shutdown_tx.send(());
+    while connections.join_next().await.is_some() {}
+}
+
+/// Accepts from whichever listener is ready. Polling starts after the
+/// listener that accepted last, so a busy listener cannot starve the others.
+async fn accept(
+    listeners: &[TcpListener],
+    next: &mut usize,
+) -> io::Result<(tokio::net::TcpStream, SocketAddr)> {
+    std::future::poll_fn(|cx| {
+        for offset in 0..listeners.len() {
+            let index = (*next + offset) % listeners.len();
+            if let Poll::Ready(accepted) = listeners[index].poll_accept(cx) {
+                *next = index + 1;
+                return Poll::Ready(accepted);
+            }
+        }
+        Poll::Pending
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_ipv6_is_recognized() {
+        assert!(ipv6_unavailable(&io::Error::from_raw_os_error(
+            EAFNOSUPPORT
+        )));
+        assert!(ipv6_unavailable(&io::Error::from(
+            io::ErrorKind::AddrNotAvailable
+        )));
+        assert!(!ipv6_unavailable(&io::Error::from(
+            io::ErrorKind::AddrInUse
+        )));
+    }
+
+    #[tokio::test]
+    async fn localhost_binds_with_or_without_ipv6() {
+        let listeners = bind(&["localhost".to_string()], 0).await.unwrap();
+        assert!(!listeners.is_empty());
+        let port = listeners[0].local_addr().unwrap().port();
+        for listener in &listeners {
+            assert_eq!(listener.local_addr().unwrap().port(), port);
+        }
+    }
+
+    #[tokio::test]
+    async fn accept_takes_turns_between_ready_listeners() {
+        let listeners = [
+            TcpListener::bind("127.0.0.1:0").await.unwrap(),
+            TcpListener::bind("127.0.0.1:0").await.unwrap(),
+        ];
+        let mut clients = Vec::new();
+        for listener in &listeners {
+            for _ in 0..2 {
+                let addr = listener.local_addr().unwrap();
+                clients.push(tokio::net::TcpStream::connect(addr).await.unwrap());
+            }
+        }
+
+        let mut next = 0;
+        let mut order = Vec::new();
+        for _ in 0..4 {
+            let (stream, _) = accept(&listeners, &mut next).await.unwrap();
+            let local = stream.local_addr().unwrap();
+            let index = listeners
+                .iter()
+                .position(|listener| listener.local_addr().unwrap() == local)
+                .unwrap();
+            order.push(index);
+        }
+        assert_eq!(order, [0, 1, 0, 1]);
+    }
+}
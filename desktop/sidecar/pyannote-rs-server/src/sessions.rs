@@ -0,0 +1,476 @@
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::task::Poll;
+
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::accounting::{self, AudioAccounting};
+use crate::clock::current_epoch_ms;
+use crate::error::AppError;
+use crate::handlers::diarize::{diarize_window, DiarizeResponse, Window};
+use crate::state::{ServerState, SessionState};
+
+/// Streaming windows a session may have queued before new ones are refused.
+const WINDOW_QUEUE_CAPACITY: usize = 16;
+
+type Job = Box<dyn FnOnce(&mut SessionState) + Send>;
+
+enum Message {
+    Window {
+        window: Box<Window>,
+        cancel: CancelToken,
+        reply: oneshot::Sender<Result<DiarizeResponse, AppError>>,
+    },
+    Job(Job),
+}
+
+/// Activity shared between a session's worker and the registry, so eviction
+/// and inactivity warnings never wait behind a busy worker.
+#[derive(Debug)]
+pub struct Activity {
+    last_seen_ms: AtomicI64,
+    inactivity_notified: AtomicBool,
+    unreported_clock_jump_ms: AtomicI64,
+}
+
+impl Activity {
+    fn new() -> Self {
+        Self {
+            last_seen_ms: AtomicI64::new(current_epoch_ms()),
+            inactivity_notified: AtomicBool::new(true),
+            unreported_clock_jump_ms: AtomicI64::new(0),
+        }
+    }
+
+    fn touch(&self) {
+        self.last_seen_ms
+            .store(current_epoch_ms(), Ordering::Relaxed);
+        self.inactivity_notified.store(false, Ordering::Relaxed);
+    }
+
+    pub fn last_seen_ms(&self) -> i64 {
+        self.last_seen_ms.load(Ordering::Relaxed)
+    }
+
+    /// Returns true only for the first caller after the last `touch`, and
+    /// never before the first, so sessions that were not streamed to, such
+    /// as offline results, are not reported as inactive.
+    pub fn mark_inactivity_notified(&self) -> bool {
+        !self.inactivity_notified.swap(true, Ordering::Relaxed)
+    }
+
+    /// Total clock jump since the session's last successful window, if any.
+    pub fn take_clock_jump_ms(&self) -> Option<i64> {
+        Some(self.unreported_clock_jump_ms.swap(0, Ordering::Relaxed))
+            .filter(|jump_ms| *jump_ms > 0)
+    }
+}
+
+/// Windows waiting for or running on a session's worker. Cancelling bumps the
+/// epoch; windows queued under an older epoch are dropped at their next check.
+#[derive(Debug, Default)]
+struct WindowQueue {
+    cancel_epoch: AtomicU64,
+    pending: AtomicUsize,
+    in_flight: AtomicBool,
+}
+
+/// Lets a window notice that its session was cancelled after it was queued.
+pub struct CancelToken {
+    queue: Arc<WindowQueue>,
+    epoch: u64,
+}
+
+impl CancelToken {
+    pub fn is_cancelled(&self) -> bool {
+        self.queue.cancel_epoch.load(Ordering::SeqCst) != self.epoch
+    }
+}
+
+pub fn window_cancelled() -> AppError {
+    AppError::conflict("window cancelled: session work was cancelled")
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Cancellation {
+    pub queued_windows: usize,
+    pub in_flight: bool,
+}
+
+/// Cheap, cloneable address of one session worker. The worker owns the
+/// session state and handles messages one at a time in arrival order, so
+/// windows of a session are processed sequentially while different sessions
+/// run in parallel. It stops once every handle is dropped and the queue is
+/// drained.
+#[derive(Debug, Clone)]
+pub struct SessionHandle {
+    session_id: Arc<str>,
+    /// Weak, as the registry inside holds this handle.
+    state: Weak<ServerState>,
+    sender: mpsc::Sender<Message>,
+    activity: Arc<Activity>,
+    queue: Arc<WindowQueue>,
+    model_generation: u64,
+}
+
+impl SessionHandle {
+    fn spawn(state: Arc<ServerState>, session_id: &str, session: SessionState) -> Self {
+        let (sender, inbox) = mpsc::channel(WINDOW_QUEUE_CAPACITY);
+        let queue = Arc::new(WindowQueue::default());
+        let handle = Self {
+            session_id: session_id.into(),
+            state: Arc::downgrade(&state),
+            sender,
+            activity: Arc::new(Activity::new()),
+            queue: queue.clone(),
+            model_generation: session.models.generation,
+        };
+        tokio::spawn(run(state, handle.session_id.clone(), session, inbox, queue));
+        handle
+    }
+
+    pub fn activity(&self) -> &Activity {
+        &self.activity
+    }
+
+    pub fn model_generation(&self) -> u64 {
+        self.model_generation
+    }
+
+    pub fn touch(&self) {
+        self.activity.touch();
+    }
+
+    /// Queues a window behind the session's earlier ones. A full queue means
+    /// the client is sending faster than the session can be diarized, which
+    /// is reported instead of buffering without bound.
+    pub async fn diarize(&self, window: Window) -> Result<DiarizeResponse, AppError> {
+        let (reply, response) = oneshot::channel();
+        let cancel = CancelToken {
+            queue: self.queue.clone(),
+            epoch: self.queue.cancel_epoch.load(Ordering::SeqCst),
+        };
+        self.queue.pending.fetch_add(1, Ordering::SeqCst);
+        self.sender
+            .try_send(Message::Window {
+                window: Box::new(window),
+                cancel,
+                reply,
+            })
+            .map_err(|error| {
+                self.queue.pending.fetch_sub(1, Ordering::SeqCst);
+                match error {
+                    TrySendError::Full(_) => AppError::unavailable(format!(
+                        "session queue is full: {WINDOW_QUEUE_CAPACITY} windows pending"
+                    )),
+                    TrySendError::Closed(_) => self.worker_stopped(),
+                }
+            })?;
+        response.await.map_err(|_| job_panicked())?
+    }
+
+    /// Cancels every window queued before this call. Queued windows are
+    /// answered with an error without touching the models; the window in
+    /// progress, if any, stops at its next check as long as it has not begun
+    /// assigning speakers, so the session state is never left half-updated.
+    /// The next window starts without pre-roll.
+    pub fn cancel(&self) -> Cancellation {
+        self.queue.cancel_epoch.fetch_add(1, Ordering::SeqCst);
+        let in_flight = self.queue.in_flight.load(Ordering::SeqCst);
+        let pending = self.queue.pending.load(Ordering::SeqCst);
+        Cancellation {
+            queued_windows: pending.saturating_sub(usize::from(in_flight)),
+            in_flight,
+        }
+    }
+
+    /// Runs `job` on the worker against the session state, after any windows
+    /// already queued.
+    pub async fn call<T, F>(&self, job: F) -> Result<T, AppError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut SessionState) -> T + Send + 'static,
+    {
+        let (reply, result) = oneshot::channel();
+        let job: Job = Box::new(move |session| {
+            let _ = reply.send(job(session));
+        });
+        self.sender
+            .send(Message::Job(job))
+            .await
+            .map_err(|_| self.worker_stopped())?;
+        result.await.map_err(|_| job_panicked())
+    }
+
+    /// Unregisters the session, whose worker is gone, so the id can be
+    /// started afresh.
+    fn worker_stopped(&self) -> AppError {
+        if let Some(state) = self.state.upgrade() {
+            state.sessions.remove_stopped(&self.session_id);
+        }
+        AppError::internal("session worker stopped unexpectedly")
+    }
+}
+
+/// The reply sender was dropped without an answer, which the worker only
+/// does while unwinding from a panic.
+fn job_panicked() -> AppError {
+    AppError::internal("session work failed unexpectedly")
+}
+
+/// Polls `future` with panics caught, like `std::panic::catch_unwind` for a
+/// future.
+async fn catch_unwind<F: Future>(future: F) -> std::thread::Result<F::Output> {
+    let mut future = std::pin::pin!(future);
+    std::future::poll_fn(|cx| {
+        match std::panic::catch_unwind(AssertUnwindSafe(|| future.as_mut().poll(cx))) {
+            Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+            Ok(Poll::Pending) => Poll::Pending,
+            Err(panic) => Poll::Ready(Err(panic)),
+        }
+    })
+    .await
+}
+
+fn report_panic(session_id: &str, panic: &(dyn std::any::Any + Send)) {
+    let message = panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown cause");
+    eprintln!("pyannote-rs sidecar session {session_id} work panicked: {message}");
+}
+
+/// Panics in a window or job are caught, so one bad request fails alone
+/// instead of taking the session down with it.
+async fn run(
+    state: Arc<ServerState>,
+    session_id: Arc<str>,
+    mut session: SessionState,
+    mut inbox: mpsc::Receiver<Message>,
+    queue: Arc<WindowQueue>,
+) {
+    let mut epoch = 0;
+    while let Some(message) = inbox.recv().await {
+        match message {
+            Message::Window {
+                window,
+                cancel,
+                reply,
+            } => {
+                // A window queued after a cancel does not continue the audio
+                // from before it, so the stored pre-roll is dropped.
+                if cancel.epoch != epoch {
+                    epoch = cancel.epoch;
+                    session.tail = None;
+                }
+                let received_ms = accounting::audio_ms(window.samples.len(), window.sample_rate);
+                let result = if cancel.is_cancelled() {
+                    Err(window_cancelled())
+                } else {
+                    // The client may have gone away; the window still counts
+                    // towards the session's speakers and timeline.
+                    queue.in_flight.store(true, Ordering::SeqCst);
+                    let _live = state.scheduler.live_window();
+                    let result =
+                        catch_unwind(diarize_window(&state, &mut session, *window, &cancel))
+                            .await
+                            .unwrap_or_else(|panic| {
+                                report_panic(&session_id, panic.as_ref());
+                                Err(job_panicked())
+                            });
+                    queue.in_flight.store(false, Ordering::SeqCst);
+                    result
+                };
+                queue.pending.fetch_sub(1, Ordering::SeqCst);
+                if result.is_err() {
+                    session.audio += AudioAccounting::failed(received_ms);
+                }
+                let _ = reply.send(result);
+            }
+            Message::Job(job) => {
+                if let Err(panic) = std::panic::catch_unwind(AssertUnwindSafe(|| job(&mut session)))
+                {
+                    report_panic(&session_id, panic.as_ref());
+                }
+            }
+        }
+    }
+}
+
+/// Maps session ids to their workers. The lock is only held to look up or
+/// insert a handle, never while a session is doing work.
+#[derive(Debug, Default)]
+pub struct SessionRegistry {
+    handles: Mutex<HashMap<String, SessionHandle>>,
+    /// Ids claimed by offline runs that have not created their session yet.
+    /// Locked after `handles` when both are needed.
+    reserved: Mutex<HashSet<String>>,
+}
+
+/// Claim on a session id from [`SessionRegistry::reserve`], released when
+/// dropped.
+#[derive(Debug)]
+pub struct Reservation {
+    state: Arc<ServerState>,
+    session_id: String,
+}
+
+impl Reservation {
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self.state
+            .sessions
+            .reserved
+            .lock()
+            .unwrap()
+            .remove(&self.session_id);
+    }
+}
+
+impl SessionRegistry {
+    pub fn get(&self, session_id: &str) -> Option<SessionHandle> {
+        self.handles.lock().unwrap().get(session_id).cloned()
+    }
+
+    pub fn all(&self) -> Vec<(String, SessionHandle)> {
+        self.handles
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(session_id, handle)| (session_id.clone(), handle.clone()))
+            .collect()
+    }
+
+    /// Returns the session's worker, starting one from `create` if the
+    /// session is new. Sessions idle for longer than `ttl_ms` are evicted
+    /// first.
+    pub fn get_or_spawn(
+        &self,
+        state: &Arc<ServerState>,
+        session_id: &str,
+        create: impl FnOnce() -> SessionState,
+    ) -> SessionHandle {
+        crate::clock::check(state);
+        let mut handles = self.handles.lock().unwrap();
+        evict_idle(&mut handles, state.config.session_ttl_ms);
+        handles
+            .entry(session_id.to_string())
+            .or_insert_with(|| SessionHandle::spawn(state.clone(), session_id, create()))
+            .clone()
+    }
+
+    /// Claims `session_id` for a session that is still being built, failing
+    /// if a session or another claim already holds it.
+    pub fn reserve(
+        &self,
+        state: &Arc<ServerState>,
+        session_id: &str,
+    ) -> Result<Reservation, AppError> {
+        let handles = self.handles.lock().unwrap();
+        let mut reserved = self.reserved.lock().unwrap();
+        if handles.contains_key(session_id) || !reserved.insert(session_id.to_string()) {
+            return Err(AppError::conflict(format!(
+                "session already exists: {session_id}"
+            )));
+        }
+        Ok(Reservation {
+            state: state.clone(),
+            session_id: session_id.to_string(),
+        })
+    }
+
+    /// Starts a worker for a fully built session under its reserved id,
+    /// failing if a streaming window created the session meanwhile.
+    pub fn insert(
+        &self,
+        state: &Arc<ServerState>,
+        reservation: Reservation,
+        session: SessionState,
+    ) -> Result<SessionHandle, AppError> {
+        crate::clock::check(state);
+        let session_id = reservation.session_id();
+        let mut handles = self.handles.lock().unwrap();
+        evict_idle(&mut handles, state.config.session_ttl_ms);
+        if handles.contains_key(session_id) {
+            return Err(AppError::conflict(format!(
+                "session already exists: {session_id}"
+            )));
+        }
+        let handle = SessionHandle::spawn(state.clone(), session_id, session);
+        handles.insert(session_id.to_string(), handle.clone());
+        Ok(handle)
+    }
+
+    /// Removes the session if its registered worker has stopped, leaving a
+    /// session started since under the same id alone.
+    fn remove_stopped(&self, session_id: &str) {
+        let mut handles = self.handles.lock().unwrap();
+        if handles
+            .get(session_id)
+            .is_some_and(|handle| handle.sender.is_closed())
+        {
+            handles.remove(session_id);
+        }
+    }
+
+    /// Moves every session's last activity forward by a clock jump, so time
+    /// the host spent asleep does not count as idleness.
+    pub fn defer_idle(&self, jump_ms: i64) {
+        for handle in self.handles.lock().unwrap().values() {
+            handle
+                .activity
+                .last_seen_ms
+                .fetch_add(jump_ms, Ordering::Relaxed);
+            handle
+                .activity
+                .unreported_clock_jump_ms
+                .fetch_add(jump_ms, Ordering::Relaxed);
+        }
+    }
+}
+
+fn evict_idle(handles: &mut HashMap<String, SessionHandle>, ttl_ms: i64) {
+    let now_ms = current_epoch_ms();
+    handles.retain(|_, handle| now_ms - handle.activity.last_seen_ms() <= ttl_ms);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn catch_unwind_contains_a_panicking_future() {
+        assert_eq!(catch_unwind(async { 7 }).await.ok(), Some(7));
+
+        let panicked = catch_unwind(async {
+            tokio::task::yield_now().await;
+            panic!("window blew up");
+        })
+        .await;
+        let panic = panicked.expect_err("panic caught");
+        assert_eq!(panic.downcast_ref::<&str>(), Some(&"window blew up"));
+    }
+
+    #[test]
+    fn inactivity_is_only_reported_once_streamed() {
+        let activity = Activity::new();
+        assert!(!activity.mark_inactivity_notified());
+
+        activity.touch();
+        assert!(activity.mark_inactivity_notified());
+        assert!(!activity.mark_inactivity_notified());
+
+        activity.touch();
+        assert!(activity.mark_inactivity_notified());
+    }
+}
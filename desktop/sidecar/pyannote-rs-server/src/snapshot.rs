@@ -0,0 +1,172 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::handlers::offline::OfflineOptions;
+use crate::models::ModelSet;
+use crate::report::csv_field;
+use crate::scoring::{Metric, Normalization, Scorer};
+use crate::state::Config;
+use crate::{gating, postprocess};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelSnapshot {
+    pub segmentation_model: String,
+    pub embedding_model: String,
+    pub generation: u64,
+}
+
+impl ModelSnapshot {
+    fn new(models: &ModelSet) -> Self {
+        Self {
+            segmentation_model: models.segmentation_model.to_string_lossy().to_string(),
+            embedding_model: models.embedding_model.to_string_lossy().to_string(),
+            generation: models.generation,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScoringSnapshot {
+    pub metric: Metric,
+    pub normalization: Normalization,
+    pub plda_dim: Option<usize>,
+}
+
+impl ScoringSnapshot {
+    fn new(scorer: &Scorer) -> Self {
+        Self {
+            metric: scorer.metric(),
+            normalization: scorer.normalization(),
+            plda_dim: scorer.expected_dim(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BudgetSnapshot {
+    pub soft_ms: i64,
+    pub hard_ms: i64,
+}
+
+/// Everything that shaped a session's results, taken when the session
+/// started. Exports carry it so that results can be reproduced and compared
+/// across sidecar versions later.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigSnapshot {
+    pub server_version: &'static str,
+    pub models: ModelSnapshot,
+    pub scoring: ScoringSnapshot,
+    /// Server default matching threshold.
+    pub threshold: f32,
+    /// Every threshold the session's windows were actually matched with, in
+    /// first-use order; differs from `threshold` when requests override it.
+    pub thresholds_used: Vec<f32>,
+    pub max_speakers: usize,
+    pub retire_silent_speakers_ms: Option<i64>,
+    pub pre_roll_ms: i64,
+    pub embedding_gate: gating::EmbeddingGate,
+    pub latency_budgets: BTreeMap<String, BudgetSnapshot>,
+    pub postprocess: postprocess::Pipeline,
+}
+
+impl ConfigSnapshot {
+    pub fn new(config: &Config, models: &ModelSet, scorer: &Scorer, max_speakers: usize) -> Self {
+        Self {
+            server_version: env!("CARGO_PKG_VERSION"),
+            models: ModelSnapshot::new(models),
+            scoring: ScoringSnapshot::new(scorer),
+            threshold: config.threshold,
+            thresholds_used: Vec::new(),
+            max_speakers,
+            retire_silent_speakers_ms: config.retire_silent_speakers_ms,
+            pre_roll_ms: config.pre_roll_ms,
+            embedding_gate: config.embedding_gate,
+            latency_budgets: config
+                .latency_budgets
+                .iter()
+                .map(|(endpoint, budget)| {
+                    (
+                        endpoint.clone(),
+                        BudgetSnapshot {
+                            soft_ms: budget.soft.as_millis() as i64,
+                            hard_ms: budget.hard.as_millis() as i64,
+                        },
+                    )
+                })
+                .collect(),
+            postprocess: config.postprocess.clone(),
+        }
+    }
+
+    /// Snapshot for a recording diarized by the `offline` command, which has
+    /// no server configuration.
+    pub fn offline(
+        models: &ModelSet,
+        scorer: &Scorer,
+        postprocess: &postprocess::Pipeline,
+        options: &OfflineOptions,
+    ) -> Self {
+        Self {
+            server_version: env!("CARGO_PKG_VERSION"),
+            models: ModelSnapshot::new(models),
+            scoring: ScoringSnapshot::new(scorer),
+            threshold: options.threshold,
+            thresholds_used: vec![options.threshold],
+            max_speakers: options.max_speakers,
+            retire_silent_speakers_ms: None,
+            pre_roll_ms: 0,
+            embedding_gate: options.gate,
+            latency_budgets: BTreeMap::new(),
+            postprocess: postprocess.clone(),
+        }
+    }
+
+    pub fn record_threshold(&mut self, threshold: f32) {
+        if !self.thresholds_used.contains(&threshold) {
+            self.thresholds_used.push(threshold);
+        }
+    }
+}
+
+/// The snapshot flattened to `key,value` CSV rows with dotted keys, e.g.
+/// `models.embedding_model`. Array elements are keyed by index.
+pub fn config_csv(snapshot: &ConfigSnapshot) -> String {
+    // Round-tripping through text keeps f32 fields at their shortest form
+    // (0.52, not 0.5199999809265137).
+    let value = serde_json::to_string(snapshot)
+        .and_then(|json| serde_json::from_str(&json))
+        .unwrap_or_default();
+    let mut rows = Vec::new();
+    flatten(String::new(), &value, &mut rows);
+    let mut csv = String::from("key,value\n");
+    for (key, value) in rows {
+        csv.push_str(&format!("{},{}\n", csv_field(&key), csv_field(&value)));
+    }
+    csv
+}
+
+fn flatten(prefix: String, value: &serde_json::Value, rows: &mut Vec<(String, String)>) {
+    let join = |key: &str| {
+        if prefix.is_empty() {
+            key.to_string()
+        } else {
+            format!("{prefix}.{key}")
+        }
+    };
+    match value {
+        serde_json::Value::Object(fields) => {
+            for (key, field) in fields {
+                flatten(join(key), field, rows);
+            }
+        }
+        serde_json::Value::Array(items) => {
+            for (index, item) in items.iter().enumerate() {
+                flatten(join(&index.to_string()), item, rows);
+            }
+        }
+        serde_json::Value::String(text) => rows.push((prefix, text.clone())),
+        serde_json::Value::Null => rows.push((prefix, String::new())),
+        other => rows.push((prefix, other.to_string())),
+    }
+}
@@ -0,0 +1,341 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::scoring::Scorer;
+use crate::timeline::StoredEmbedding;
+use crate::track::{parse_speaker_id, Track, UNATTRIBUTED_SPEAKER_ID};
+
+/// Per-session speaker pool. Behaves like `pyannote_rs::EmbeddingManager` but
+/// scores candidates through the configured [`Scorer`] instead of a hardcoded
+/// cosine similarity.
+#[derive(Debug, Clone)]
+pub struct SpeakerRegistry {
+    max_speakers: usize,
+    speakers: BTreeMap<usize, Vec<f32>>,
+    /// Voiceprints taken out of matching by [`Self::retire_silent`]. They
+    /// keep their ids and stay erasable, but no longer attract new segments.
+    retired: BTreeMap<usize, Vec<f32>>,
+    last_heard_ms: BTreeMap<usize, i64>,
+    /// Running sums behind [`SpeakerDelta`], so a window only pays for its
+    /// own segments.
+    totals: BTreeMap<usize, SpeakerTotals>,
+    next_speaker_id: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct SpeakerTotals {
+    speech_ms: i64,
+    segment_count: usize,
+    /// Scores of the speaker's embedded segments against the voiceprint
+    /// current when each was recorded.
+    score_sum: f32,
+}
+
+impl SpeakerRegistry {
+    pub fn new(max_speakers: usize) -> Self {
+        Self {
+            max_speakers,
+            speakers: BTreeMap::new(),
+            retired: BTreeMap::new(),
+            last_heard_ms: BTreeMap::new(),
+            totals: BTreeMap::new(),
+            next_speaker_id: 1,
+        }
+    }
+
+    fn best_scoring(&self, embedding: &[f32], scorer: &Scorer) -> Option<(usize, f32)> {
+        let mut best: Option<(usize, f32)> = None;
+        for (&speaker_id, speaker_embedding) in &self.speakers {
+            let score = scorer.score(embedding, speaker_embedding);
+            if best.is_none_or(|(_, best_score)| score > best_score) {
+                best = Some((speaker_id, score));
+            }
+        }
+        best
+    }
+
+    /// Returns the best speaker scoring above `threshold`, or registers the
+    /// embedding as a new speaker while the pool has room. Retired speakers
+    /// neither match nor take up room.
+    pub fn search_speaker(
+        &mut self,
+        embedding: Vec<f32>,
+        threshold: f32,
+        scorer: &Scorer,
+    ) -> Option<usize> {
+        match self.best_scoring(&embedding, scorer) {
+            Some((speaker_id, score)) if score > threshold => Some(speaker_id),
+            _ if self.speakers.len() < self.max_speakers => Some(self.add_speaker(embedding)),
+            _ => None,
+        }
+    }
+
+    pub fn best_speaker_match(&self, embedding: &[f32], scorer: &Scorer) -> Option<usize> {
+        self.best_scoring(embedding, scorer)
+            .map(|(speaker_id, _)| speaker_id)
+    }
+
+    pub fn set_speaker(&mut self, speaker_id: usize, embedding: Vec<f32>) {
+        if let Some(stored) = self
+            .speakers
+            .get_mut(&speaker_id)
+            .or_else(|| self.retired.get_mut(&speaker_id))
+        {
+            *stored = embedding;
+        }
+    }
+
+    /// Drops the speaker, active or retired. Ids are never reused, so a later
+    /// speaker cannot inherit the removed one's label.
+    pub fn remove_speaker(&mut self, speaker_id: usize) -> bool {
+        self.last_heard_ms.remove(&speaker_id);
+        self.totals.remove(&speaker_id);
+        let active = self.speakers.remove(&speaker_id).is_some();
+        let retired = self.retired.remove(&speaker_id).is_some();
+        active || retired
+    }
+
+    /// Records speech from the speaker ending at `at_ms` on the session
+    /// timeline.
+    pub fn heard(&mut self, speaker_id: usize, at_ms: i64) {
+        let last = self.last_heard_ms.entry(speaker_id).or_insert(at_ms);
+        *last = (*last).max(at_ms);
+    }
+
+    /// Retires active speakers last heard at least `silence_ms` before
+    /// `now_ms` and returns their ids. Speakers never passed to
+    /// [`Self::heard`] are left alone. A retired speaker who talks again is
+    /// enrolled under a new id.
+    pub fn retire_silent(&mut self, now_ms: i64, silence_ms: i64) -> Vec<usize> {
+        let silent: Vec<usize> = self
+            .speakers
+            .keys()
+            .copied()
+            .filter(|speaker_id| {
+                self.last_heard_ms
+                    .get(speaker_id)
+                    .is_some_and(|&last_ms| now_ms - last_ms >= silence_ms)
+            })
+            .collect();
+        for speaker_id in &silent {
+            if let Some(embedding) = self.speakers.remove(speaker_id) {
+                self.retired.insert(*speaker_id, embedding);
+            }
+        }
+        silent
+    }
+
+    /// Id the next enrolled speaker will get; every id at or above it is
+    /// still unused.
+    pub fn next_speaker_id(&self) -> usize {
+        self.next_speaker_id
+    }
+
+    /// Matching voiceprint of an active or retired speaker.
+    pub fn voiceprint(&self, speaker_id: usize) -> Option<&[f32]> {
+        self.speakers
+            .get(&speaker_id)
+            .or_else(|| self.retired.get(&speaker_id))
+            .map(Vec::as_slice)
+    }
+
+    /// Adds `tracks` and the embedded segments among them to the running
+    /// totals. Segments are scored against the speaker's current voiceprint.
+    pub fn record(&mut self, embeddings: &[StoredEmbedding], tracks: &[Track], scorer: &Scorer) {
+        for stored in embeddings {
+            let Some(voiceprint) = self.voiceprint(stored.speaker_id) else {
+                continue;
+            };
+            let score = scorer.score(&stored.embedding, voiceprint);
+            let totals = self.totals.entry(stored.speaker_id).or_default();
+            totals.segment_count += 1;
+            totals.score_sum += score;
+        }
+        for track in tracks {
+            if let Some(speaker_id) = parse_speaker_id(&track.speaker_id) {
+                self.totals.entry(speaker_id).or_default().speech_ms +=
+                    track.end_ms - track.start_ms;
+            }
+        }
+    }
+
+    /// Rebuilds the running totals from the whole session, after voiceprints
+    /// or the timeline changed other than by appending a window.
+    pub fn recount(&mut self, embeddings: &[StoredEmbedding], timeline: &[Track], scorer: &Scorer) {
+        self.totals.clear();
+        self.record(embeddings, timeline, scorer);
+    }
+
+    /// See [`SpeakerDelta::centroid_confidence`].
+    pub fn centroid_confidence(&self, speaker_id: usize) -> Option<f32> {
+        let totals = self.totals.get(&speaker_id)?;
+        (totals.segment_count > 0)
+            .then(|| (totals.score_sum / totals.segment_count as f32).min(1.0))
+    }
+
+    pub fn add_speaker(&mut self, embedding: Vec<f32>) -> usize {
+        let speaker_id = self.next_speaker_id;
+        self.speakers.insert(speaker_id, embedding);
+        self.next_speaker_id += 1;
+        speaker_id
+    }
+}
+
+/// Roster entry for a speaker a window created or added speech to.
+#[derive(Debug, Clone, Serialize)]
+pub struct SpeakerDelta {
+    pub speaker_id: String,
+    /// Enrolled by this window.
+    pub created: bool,
+    /// Mean score of the speaker's embedded segments against their
+    /// voiceprint, on the matching threshold's scale. A low value means the
+    /// voiceprint is a poor fit for what was assigned to it. `None` for
+    /// speakers without embedded segments.
+    pub centroid_confidence: Option<f32>,
+    pub segment_count: usize,
+    /// Attributed speech so far, this window included.
+    pub speech_ms: i64,
+}
+
+/// One [`SpeakerDelta`] per distinct speaker in `tracks`, in id order, read
+/// from the registry's running totals. Speakers with an id of at least
+/// `first_new_id` count as created.
+pub fn deltas(
+    registry: &SpeakerRegistry,
+    tracks: &[Track],
+    first_new_id: usize,
+) -> Vec<SpeakerDelta> {
+    let mut touched: Vec<usize> = tracks
+        .iter()
+        .filter(|track| track.speaker_id != UNATTRIBUTED_SPEAKER_ID)
+        .filter_map(|track| parse_speaker_id(&track.speaker_id))
+        .collect();
+    touched.sort_unstable();
+    touched.dedup();
+
+    touched
+        .into_iter()
+        .map(|speaker_id| {
+            let totals = registry
+                .totals
+                .get(&speaker_id)
+                .copied()
+                .unwrap_or_default();
+            SpeakerDelta {
+                speaker_id: format!("edge_spk_{speaker_id}"),
+                created: speaker_id >= first_new_id,
+                centroid_confidence: registry.centroid_confidence(speaker_id),
+                segment_count: totals.segment_count,
+                speech_ms: totals.speech_ms,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stored(speaker_id: usize, start_ms: i64, embedding: &[f32]) -> StoredEmbedding {
+        StoredEmbedding {
+            speaker_id,
+            start_ms,
+            end_ms: start_ms + 1000,
+            embedding: embedding.to_vec(),
+        }
+    }
+
+    #[test]
+    fn deltas_accumulate_across_windows() {
+        let scorer = Scorer::cosine();
+        let mut registry = SpeakerRegistry::new(4);
+        let first = registry.add_speaker(vec![1.0, 0.0]);
+
+        registry.record(
+            &[stored(first, 0, &[1.0, 0.0])],
+            &[Track::test("edge_spk_1", 0, 1000)],
+            &scorer,
+        );
+        let window = [Track::test("edge_spk_1", 1000, 3000)];
+        registry.record(&[stored(first, 1000, &[0.0, 1.0])], &window, &scorer);
+
+        let deltas = deltas(&registry, &window, 2);
+        assert_eq!(deltas.len(), 1);
+        assert_eq!(deltas[0].speaker_id, "edge_spk_1");
+        assert!(!deltas[0].created);
+        assert_eq!(deltas[0].segment_count, 2);
+        assert_eq!(deltas[0].speech_ms, 3000);
+        assert_eq!(deltas[0].centroid_confidence, Some(0.5));
+    }
+
+    #[test]
+    fn recount_replaces_running_totals() {
+        let scorer = Scorer::cosine();
+        let mut registry = SpeakerRegistry::new(4);
+        let speaker_id = registry.add_speaker(vec![1.0, 0.0]);
+        let timeline = [Track::test("edge_spk_1", 0, 2000)];
+        registry.record(&[stored(speaker_id, 0, &[0.0, 1.0])], &timeline, &scorer);
+
+        registry.recount(
+            &[stored(speaker_id, 0, &[1.0, 0.0])],
+            &timeline[..0],
+            &scorer,
+        );
+        assert_eq!(registry.centroid_confidence(speaker_id), Some(1.0));
+        let deltas = deltas(&registry, &timeline, 1);
+        assert_eq!(deltas[0].speech_ms, 0);
+        assert_eq!(deltas[0].segment_count, 1);
+        assert!(deltas[0].created);
+    }
+
+    #[test]
+    fn silent_speakers_stop_matching_after_the_silence_limit() {
+        let scorer = Scorer::cosine();
+        let mut registry = SpeakerRegistry::new(4);
+        let quiet = registry.add_speaker(vec![1.0, 0.0]);
+        let talkative = registry.add_speaker(vec![0.0, 1.0]);
+        registry.heard(quiet, 1_000);
+        registry.heard(talkative, 50_000);
+
+        assert!(registry.retire_silent(30_999, 30_000).is_empty());
+        assert_eq!(registry.retire_silent(31_000, 30_000), [quiet]);
+        assert!(registry.voiceprint(quiet).is_some());
+        assert_eq!(
+            registry.best_speaker_match(&[1.0, 0.1], &scorer),
+            Some(talkative)
+        );
+
+        let returning = registry.search_speaker(vec![1.0, 0.0], 0.5, &scorer);
+        assert_eq!(returning, Some(talkative + 1));
+        assert!(registry.retire_silent(31_000, 30_000).is_empty());
+    }
+
+    #[test]
+    fn unheard_speakers_are_never_retired() {
+        let mut registry = SpeakerRegistry::new(4);
+        let speaker_id = registry.add_speaker(vec![1.0, 0.0]);
+        assert!(registry.retire_silent(i64::MAX, 1).is_empty());
+        assert!(registry.voiceprint(speaker_id).is_some());
+    }
+
+    #[test]
+    fn retired_and_removed_ids_are_not_reused() {
+        let scorer = Scorer::cosine();
+        let mut registry = SpeakerRegistry::new(2);
+        let first = registry.add_speaker(vec![1.0, 0.0]);
+        let second = registry.add_speaker(vec![0.0, 1.0]);
+        registry.heard(first, 0);
+        registry.retire_silent(10_000, 10_000);
+        // A retired speaker no longer takes up room in the pool.
+        let third = registry.search_speaker(vec![-1.0, 0.0], 0.5, &scorer);
+        assert_eq!(third, Some(second + 1));
+
+        assert!(registry.remove_speaker(first));
+        assert!(registry.remove_speaker(second));
+        assert!(!registry.remove_speaker(first));
+        let fourth = registry.add_speaker(vec![1.0, 1.0]);
+        assert!(fourth > third.unwrap());
+        assert_eq!(registry.next_speaker_id(), fourth + 1);
+    }
+}
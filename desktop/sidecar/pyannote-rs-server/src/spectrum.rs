@@ -0,0 +1,207 @@
+use std::f64::consts::PI;
+
+const FRAME_LEN: usize = 512;
+
+/// Frames averaged into the spectrum; longer audio is sampled evenly.
+const MAX_FRAMES: usize = 64;
+
+/// Fewer loud frames than this and the audio is not judged at all.
+const MIN_FRAMES: usize = 16;
+
+/// Frame RMS below which a frame is skipped as silence, about -50 dBFS.
+const SILENCE_RMS: f64 = 100.0;
+
+/// Share of spectral energy below the rolloff frequency.
+const ROLLOFF_FRACTION: f64 = 0.95;
+
+/// Typical speech rolloff at a correct sample rate.
+const TYPICAL_ROLLOFF_HZ: f64 = 3500.0;
+
+/// Largest factor between the measured and the typical rolloff still taken
+/// as speech at the declared rate, wide enough for muffled or hissy
+/// recordings. Mislabels between common rates are mostly 2x or more.
+const MAX_ROLLOFF_FACTOR: f64 = 2.0;
+
+const COMMON_RATES: [u32; 7] = [8000, 16000, 22050, 24000, 32000, 44100, 48000];
+
+/// Compares the declared sample rate with where the audio's energy lies.
+/// Audio captured at 48 kHz but declared as 16 kHz plays three times too
+/// slow, so its rolloff sits a third of where speech puts it, and the
+/// reverse pushes it up. Returns a `SAMPLE_RATE_SUSPECT` warning naming the
+/// common rate that would make the rolloff typical of speech.
+pub fn sample_rate_warning(samples: &[i16], sample_rate: u32) -> Option<String> {
+    let rolloff_hz = rolloff_hz(samples, sample_rate)?;
+    let likely = likely_rate(rolloff_hz, sample_rate)?;
+    let direction = if rolloff_hz < TYPICAL_ROLLOFF_HZ {
+        "low"
+    } else {
+        "high"
+    };
+    Some(format!(
+        "SAMPLE_RATE_SUSPECT: at the declared sample_rate of {sample_rate} Hz the audio's spectral rolloff is \
+         {rolloff_hz:.0} Hz, too {direction} for speech; it may have been captured at {likely} Hz"
+    ))
+}
+
+/// The common rate under which `rolloff_hz`, measured at `declared`, comes
+/// closest to typical speech, or `None` when the declared rate is plausible
+/// or no common rate fits better.
+fn likely_rate(rolloff_hz: f64, declared: u32) -> Option<u32> {
+    // Log distance, so that 3x too low and 3x too high weigh the same.
+    let distance = |rate: u32| {
+        (rolloff_hz * rate as f64 / declared as f64 / TYPICAL_ROLLOFF_HZ)
+            .ln()
+            .abs()
+    };
+    if distance(declared) <= MAX_ROLLOFF_FACTOR.ln() {
+        return None;
+    }
+    let likely = COMMON_RATES
+        .into_iter()
+        .min_by(|a, b| distance(*a).total_cmp(&distance(*b)))?;
+    (likely != declared && distance(likely) < distance(declared)).then_some(likely)
+}
+
+/// Frequency below which `ROLLOFF_FRACTION` of the energy of the averaged
+/// power spectrum lies, or `None` when there is too little loud audio.
+fn rolloff_hz(samples: &[i16], sample_rate: u32) -> Option<f64> {
+    let loud: Vec<&[i16]> = samples
+        .chunks_exact(FRAME_LEN)
+        .filter(|frame| {
+            let energy: f64 = frame.iter().map(|&sample| (sample as f64).powi(2)).sum();
+            (energy / FRAME_LEN as f64).sqrt() >= SILENCE_RMS
+        })
+        .collect();
+    if loud.len() < MIN_FRAMES {
+        return None;
+    }
+
+    let stride = loud.len().div_ceil(MAX_FRAMES);
+    let mut power = vec![0.0f64; FRAME_LEN / 2 + 1];
+    for frame in loud.iter().step_by(stride) {
+        let mut bins: Vec<(f64, f64)> = frame
+            .iter()
+            .enumerate()
+            .map(|(index, &sample)| {
+                let hann = 0.5 - 0.5 * (2.0 * PI * index as f64 / FRAME_LEN as f64).cos();
+                (sample as f64 * hann, 0.0)
+            })
+            .collect();
+        fft(&mut bins);
+        for (total, (re, im)) in power.iter_mut().zip(&bins) {
+            *total += re * re + im * im;
+        }
+    }
+
+    // The DC bin says nothing about the rate and offsets would skew it.
+    power[0] = 0.0;
+    let total: f64 = power.iter().sum();
+    if total == 0.0 {
+        return None;
+    }
+    let mut cumulative = 0.0;
+    let bin = power
+        .iter()
+        .position(|value| {
+            cumulative += value;
+            cumulative >= total * ROLLOFF_FRACTION
+        })
+        .unwrap_or(power.len() - 1);
+    Some(bin as f64 * sample_rate as f64 / FRAME_LEN as f64)
+}
+
+/// In-place iterative radix-2 FFT; `data.len()` must be a power of two.
+fn fft(data: &mut [(f64, f64)]) {
+    let n = data.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            data.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f64;
+        for chunk in data.chunks_exact_mut(len) {
+            let (low, high) = chunk.split_at_mut(len / 2);
+            for (k, (a, b)) in low.iter_mut().zip(high.iter_mut()).enumerate() {
+                let (sin, cos) = (angle * k as f64).sin_cos();
+                let t = (b.0 * cos - b.1 * sin, b.0 * sin + b.1 * cos);
+                *b = (a.0 - t.0, a.1 - t.1);
+                *a = (a.0 + t.0, a.1 + t.1);
+            }
+        }
+        len <<= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two seconds of a voice-like signal captured at `rate`: a 150 Hz
+    /// harmonic series up to 7 kHz with a falling envelope, plus a little
+    /// broadband noise.
+    fn voice(rate: u32) -> Vec<i16> {
+        let mut seed = 1u64;
+        (0..rate as usize * 2)
+            .map(|index| {
+                let t = index as f64 / rate as f64;
+                let voiced: f64 = (1..=46)
+                    .map(|harmonic| {
+                        let frequency = 150.0 * harmonic as f64;
+                        (2.0 * PI * frequency * t).sin() * 3000.0 / (1.0 + frequency / 500.0)
+                    })
+                    .sum();
+                seed = seed
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                let noise = ((seed >> 33) as f64 / (1u64 << 31) as f64 - 0.5) * 600.0;
+                (voiced + noise).clamp(i16::MIN as f64, i16::MAX as f64) as i16
+            })
+            .collect()
+    }
+
+    #[test]
+    fn correctly_declared_rates_pass() {
+        assert_eq!(sample_rate_warning(&voice(16_000), 16_000), None);
+        assert_eq!(sample_rate_warning(&voice(48_000), 48_000), None);
+    }
+
+    #[test]
+    fn flags_48k_declared_as_16k() {
+        let warning = sample_rate_warning(&voice(48_000), 16_000).expect("mislabel flagged");
+        assert!(warning.starts_with("SAMPLE_RATE_SUSPECT"), "{warning}");
+        // 44.1 and 48 kHz are too close to tell apart by rolloff alone.
+        assert!(
+            warning.contains("captured at 48000 Hz") || warning.contains("captured at 44100 Hz"),
+            "{warning}"
+        );
+    }
+
+    #[test]
+    fn flags_16k_declared_as_48k() {
+        let warning = sample_rate_warning(&voice(16_000), 48_000).expect("mislabel flagged");
+        assert!(warning.contains("captured at 16000 Hz"), "{warning}");
+    }
+
+    #[test]
+    fn likely_rate_inverts_a_3x_mislabel() {
+        assert_eq!(likely_rate(TYPICAL_ROLLOFF_HZ / 3.0, 16_000), Some(48_000));
+        assert_eq!(likely_rate(TYPICAL_ROLLOFF_HZ * 3.0, 48_000), Some(16_000));
+        assert_eq!(likely_rate(TYPICAL_ROLLOFF_HZ, 16_000), None);
+        assert_eq!(likely_rate(TYPICAL_ROLLOFF_HZ / 1.5, 16_000), None);
+    }
+
+    #[test]
+    fn quiet_audio_is_not_judged() {
+        assert_eq!(sample_rate_warning(&vec![0; 32_000], 16_000), None);
+    }
+}
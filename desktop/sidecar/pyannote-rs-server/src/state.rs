@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicU64;
+use std::sync::Arc;
+use std::time::Instant;
+
+use serde::Serialize;
+use tokio::sync::{broadcast, Mutex, RwLock};
+
+use crate::cli::LatencyBudget;
+use crate::handlers::diarize::DiarizeResponse;
+use crate::models::ModelSet;
+use crate::scoring::Scorer;
+use crate::speakers::SpeakerRegistry;
+use crate::timeline::StoredEmbedding;
+use crate::track::Track;
+use crate::{accounting, clock, events, gating, jobs, postprocess, scheduler, sessions, snapshot};
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub max_speakers: usize,
+    pub threshold: f32,
+    pub session_ttl_ms: i64,
+    pub idempotency_ttl_ms: i64,
+    pub retire_silent_speakers_ms: Option<i64>,
+    pub pre_roll_ms: i64,
+    pub embedding_gate: gating::EmbeddingGate,
+    pub latency_budgets: HashMap<String, LatencyBudget>,
+    pub postprocess: postprocess::Pipeline,
+    pub writable_dirs: Vec<(String, PathBuf)>,
+    pub min_free_disk_bytes: u64,
+    pub profile: bool,
+    pub profile_trace_dir: Option<PathBuf>,
+    pub strict_validation: bool,
+}
+
+#[derive(Debug)]
+pub struct SessionState {
+    pub manager: SpeakerRegistry,
+    pub models: Arc<ModelSet>,
+    pub idempotent_responses: HashMap<String, CachedResponse>,
+    pub window_fingerprints: HashMap<WindowFingerprint, CachedResponse>,
+    pub timeline: Vec<Track>,
+    pub embeddings: Vec<StoredEmbedding>,
+    /// Totals over every window received, including failed ones.
+    pub audio: accounting::AudioAccounting,
+    pub tail: Option<AudioTail>,
+    pub config: snapshot::ConfigSnapshot,
+}
+
+/// End of the audio a session last diarized, kept as pre-roll for the next
+/// window.
+#[derive(Debug)]
+pub struct AudioTail {
+    pub samples: Vec<i16>,
+    pub sample_rate: u32,
+    pub end_ms: i64,
+}
+
+#[derive(Debug)]
+pub struct CachedResponse {
+    pub response: DiarizeResponse,
+    /// `fingerprint_window` of the window that produced `response`.
+    pub fingerprint: WindowFingerprint,
+    pub stored_at_ms: i64,
+}
+
+/// SHA-256 over everything that makes two windows the same request.
+pub type WindowFingerprint = [u8; 32];
+
+/// State shared by every route; built by [`build_state`](crate::build_state).
+#[derive(Debug)]
+pub struct ServerState {
+    pub(crate) config: Config,
+    pub(crate) started_at: Instant,
+    pub(crate) scorer: Scorer,
+    pub(crate) models: RwLock<Arc<ModelSet>>,
+    pub(crate) next_model_generation: AtomicU64,
+    pub(crate) model_reload: Mutex<ModelReloadStatus>,
+    pub(crate) events: broadcast::Sender<events::ServerEvent>,
+    pub(crate) clock: clock::ClockMonitor,
+    pub(crate) sessions: sessions::SessionRegistry,
+    pub(crate) jobs: jobs::JobRegistry,
+    pub(crate) scheduler: Arc<scheduler::Scheduler>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ModelReloadStatus {
+    pub state: ModelReloadState,
+    pub target_generation: Option<u64>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModelReloadState {
+    #[default]
+    Idle,
+    Loading,
+    Failed,
+}
+
+/// The session timeline as responses and exports show it, with the
+/// post-processing `Relabel` steps the session ran with applied.
+pub fn labeled_timeline(session: &SessionState) -> Vec<Track> {
+    let mut timeline = session.timeline.clone();
+    session.config.postprocess.relabel(&mut timeline);
+    timeline
+}
@@ -0,0 +1,51 @@
+use std::path::{Path, PathBuf};
+
+const APP_DIR: &str = "pyannote-rs";
+
+/// Per-user directory for everything the sidecar writes, so nothing lands
+/// next to the executable, which per-machine installs cannot write to:
+///
+/// - Windows: `%LOCALAPPDATA%\pyannote-rs`
+/// - macOS: `~/Library/Application Support/pyannote-rs`
+/// - elsewhere: `$XDG_STATE_HOME/pyannote-rs`, else `~/.local/state/pyannote-rs`
+///
+/// Falls back to the system temp directory when the variables it needs are
+/// unset, e.g. under a service account without a profile.
+pub fn default_state_dir() -> PathBuf {
+    platform_state_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join(APP_DIR)
+}
+
+#[cfg(windows)]
+fn platform_state_dir() -> Option<PathBuf> {
+    env_dir("LOCALAPPDATA").or_else(|| env_dir("APPDATA"))
+}
+
+#[cfg(target_os = "macos")]
+fn platform_state_dir() -> Option<PathBuf> {
+    env_dir("HOME").map(|home| home.join("Library").join("Application Support"))
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+fn platform_state_dir() -> Option<PathBuf> {
+    env_dir("XDG_STATE_HOME")
+        .or_else(|| env_dir("HOME").map(|home| home.join(".local").join("state")))
+}
+
+/// Only absolute values count; a relative one would depend on the working
+/// directory again.
+fn env_dir(name: &str) -> Option<PathBuf> {
+    std::env::var_os(name)
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+}
+
+/// `path` itself when absolute, otherwise `path` under `state_dir`.
+pub fn resolve(state_dir: &Path, path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        state_dir.join(path)
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::state::{labeled_timeline, SessionState};
+use crate::track::Track;
+use crate::{accounting, analytics, report, snapshot, speakers};
+
+/// Silences shorter than this are ordinary pauses and not listed.
+pub const DEFAULT_MIN_SILENCE_MS: i64 = 2000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Interviewer,
+    Interviewee,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SummarySpeaker {
+    pub speaker_id: String,
+    /// Display name supplied by the caller, if any.
+    pub label: Option<String>,
+    pub role: Role,
+    pub track_count: usize,
+    pub turn_count: usize,
+    pub speech_ms: i64,
+    /// Fraction of all attributed speech, in [0, 1].
+    pub speech_share: f64,
+    pub first_start_ms: i64,
+    pub last_end_ms: i64,
+    /// Turns this speaker took while the previous speaker was still talking.
+    pub interruptions_made: usize,
+    pub interruptions_received: usize,
+    /// See [`speakers::SpeakerDelta::centroid_confidence`].
+    pub centroid_confidence: Option<f32>,
+}
+
+/// A turn that started before the previous speaker's turn ended.
+#[derive(Debug, Serialize)]
+pub struct Interruption {
+    pub speaker_id: String,
+    pub interrupted_speaker_id: String,
+    pub at_ms: i64,
+    pub overlap_ms: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Silence {
+    pub start_ms: i64,
+    pub end_ms: i64,
+    pub duration_ms: i64,
+}
+
+/// Everything a finished interview's feedback record needs in one document.
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    pub session_id: String,
+    pub generated_at_ms: i64,
+    /// From session time zero to the end of the last track.
+    pub duration_ms: i64,
+    pub speaker_count: usize,
+    pub speakers: Vec<SummarySpeaker>,
+    pub timeline: Vec<Track>,
+    pub interruptions: Vec<Interruption>,
+    /// Stretches of at least `min_silence_ms` without attributed speech,
+    /// including any before the first track.
+    pub silences: Vec<Silence>,
+    pub silence_ms: i64,
+    pub analytics: analytics::AnalyticsResponse,
+    pub audio: accounting::AudioAccounting,
+    pub quality_warnings: Vec<String>,
+    pub config: snapshot::ConfigSnapshot,
+}
+
+pub struct SummaryOptions<'a> {
+    pub interviewer: Option<&'a str>,
+    pub labels: &'a BTreeMap<String, String>,
+    pub min_silence_ms: i64,
+}
+
+pub fn summarize(
+    session_id: &str,
+    session: &SessionState,
+    options: SummaryOptions<'_>,
+    generated_at_ms: i64,
+) -> Summary {
+    let timeline = &labeled_timeline(session);
+    let turns = analytics::build_turns(timeline);
+    let analytics = analytics::analyze(session_id, timeline, options.interviewer);
+    let interviewer = analytics.interviewer_speaker_id.as_deref();
+    let interruptions = interruptions(&turns);
+    let confidence: BTreeMap<String, Option<f32>> =
+        speakers::deltas(&session.manager, &session.timeline, usize::MAX)
+            .into_iter()
+            .map(|delta| {
+                let label = session.config.postprocess.label(&delta.speaker_id);
+                (label.to_string(), delta.centroid_confidence)
+            })
+            .collect();
+
+    let speakers: Vec<SummarySpeaker> = report::speaker_stats(timeline)
+        .into_iter()
+        .map(|stats| {
+            let speaker_id = stats.speaker_id;
+            SummarySpeaker {
+                label: options.labels.get(&speaker_id).cloned(),
+                role: if interviewer == Some(speaker_id.as_str()) {
+                    Role::Interviewer
+                } else {
+                    Role::Interviewee
+                },
+                track_count: stats.track_count,
+                turn_count: turns
+                    .iter()
+                    .filter(|turn| turn.speaker_id == speaker_id)
+                    .count(),
+                speech_ms: stats.speech_ms,
+                speech_share: stats.speech_share,
+                first_start_ms: stats.first_start_ms,
+                last_end_ms: stats.last_end_ms,
+                interruptions_made: interruptions
+                    .iter()
+                    .filter(|interruption| interruption.speaker_id == speaker_id)
+                    .count(),
+                interruptions_received: interruptions
+                    .iter()
+                    .filter(|interruption| interruption.interrupted_speaker_id == speaker_id)
+                    .count(),
+                centroid_confidence: confidence.get(&speaker_id).copied().flatten(),
+                speaker_id,
+            }
+        })
+        .collect();
+
+    let duration_ms = timeline.iter().map(|track| track.end_ms).max().unwrap_or(0);
+    let silences = silences(timeline, duration_ms, options.min_silence_ms);
+    let quality_warnings = quality_warnings(session, &speakers);
+
+    Summary {
+        session_id: session_id.to_string(),
+        generated_at_ms,
+        duration_ms,
+        speaker_count: speakers.len(),
+        speakers,
+        timeline: timeline.to_vec(),
+        interruptions,
+        silence_ms: silences.iter().map(|silence| silence.duration_ms).sum(),
+        silences,
+        analytics,
+        audio: session.audio,
+        quality_warnings,
+        config: session.config.clone(),
+    }
+}
+
+fn interruptions(turns: &[analytics::Turn]) -> Vec<Interruption> {
+    turns
+        .windows(2)
+        .filter(|pair| pair[1].start_ms < pair[0].end_ms)
+        .map(|pair| Interruption {
+            speaker_id: pair[1].speaker_id.clone(),
+            interrupted_speaker_id: pair[0].speaker_id.clone(),
+            at_ms: pair[1].start_ms,
+            overlap_ms: pair[0].end_ms.min(pair[1].end_ms) - pair[1].start_ms,
+        })
+        .collect()
+}
+
+fn silences(timeline: &[Track], end_ms: i64, min_silence_ms: i64) -> Vec<Silence> {
+    let mut spans: Vec<(i64, i64)> = timeline
+        .iter()
+        .map(|track| (track.start_ms, track.end_ms))
+        .collect();
+    spans.sort_unstable();
+
+    let mut silences = Vec::new();
+    let mut cursor = 0;
+    for (start_ms, end_ms) in spans.into_iter().chain([(end_ms, end_ms)]) {
+        if start_ms - cursor >= min_silence_ms {
+            silences.push(Silence {
+                start_ms: cursor,
+                end_ms: start_ms,
+                duration_ms: start_ms - cursor,
+            });
+        }
+        cursor = cursor.max(end_ms);
+    }
+    silences
+}
+
+fn quality_warnings(session: &SessionState, speakers: &[SummarySpeaker]) -> Vec<String> {
+    let audio = &session.audio;
+    let mut warnings = Vec::new();
+    if audio.failed_ms > 0 {
+        warnings.push(format!("{}ms of audio failed to process", audio.failed_ms));
+    }
+    if audio.unassigned_ms > 0 {
+        warnings.push(format!(
+            "{}ms of speech could not be assigned to a speaker",
+            audio.unassigned_ms
+        ));
+    }
+    if audio.segment_errors > 0 {
+        warnings.push(format!(
+            "{} segment(s) failed segmentation",
+            audio.segment_errors
+        ));
+    }
+    match speakers.len() {
+        0 => warnings.push("no speech was attributed to any speaker".to_string()),
+        1 => warnings.push("only one speaker was detected".to_string()),
+        _ => {}
+    }
+    // Below every threshold the session matched with, the voiceprint would
+    // not even have matched its own average segment.
+    let threshold = session
+        .config
+        .thresholds_used
+        .iter()
+        .copied()
+        .reduce(f32::min)
+        .unwrap_or(session.config.threshold);
+    for speaker in speakers {
+        if let Some(confidence) = speaker.centroid_confidence {
+            if confidence < threshold {
+                warnings.push(format!(
+                    "{} fits their voiceprint poorly (mean score {confidence:.2}), speech of several people may be merged",
+                    speaker.speaker_id
+                ));
+            }
+        }
+    }
+    warnings
+}
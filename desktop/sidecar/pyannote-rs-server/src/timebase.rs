@@ -0,0 +1,161 @@
+use serde::{Deserialize, Serialize};
+
+use crate::track::Track;
+
+/// Unit for the extra per-track time fields. Millisecond fields are always
+/// present and keep their historic rounding. The other units are derived from
+/// the unrounded segment boundaries, never from the millisecond values, so
+/// they carry no double rounding:
+///
+/// - `samples`: nearest sample index at the request's sample rate, with ties
+///   rounded away from zero;
+/// - `seconds`: fractional seconds rounded to the nearest microsecond, which
+///   is finer than one sample at any supported rate.
+///
+/// Durations are `end - start` in the unit, taken after rounding. Added back
+/// to `start` they give exactly `end` in milliseconds and samples; in seconds
+/// they agree to the microsecond, though the `f64` sum may differ from `end`
+/// in its last bits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeUnit {
+    #[default]
+    Ms,
+    Samples,
+    Seconds,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(untagged)]
+pub enum TimeValue {
+    Samples(i64),
+    Seconds(f64),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UnitTimes {
+    pub start: TimeValue,
+    pub end: TimeValue,
+    pub duration: TimeValue,
+    pub local_start: TimeValue,
+    pub local_end: TimeValue,
+}
+
+fn round_micros(seconds: f64) -> f64 {
+    (seconds * 1_000_000.0).round() / 1_000_000.0
+}
+
+pub fn apply(tracks: &mut [Track], unit: TimeUnit, sample_rate: u32) {
+    for track in tracks {
+        // Offset between session and window time, exact in integral ms.
+        let local_offset_s = (track.local_start_ms - track.start_ms) as f64 / 1000.0;
+        let (start_s, end_s) = (track.exact_start_s, track.exact_end_s);
+
+        track.unit_times = match unit {
+            TimeUnit::Ms => None,
+            TimeUnit::Samples => {
+                let to_samples = |seconds: f64| (seconds * sample_rate as f64).round() as i64;
+                let (start, end) = (to_samples(start_s), to_samples(end_s));
+                let (local_start, local_end) = (
+                    to_samples(start_s + local_offset_s),
+                    to_samples(end_s + local_offset_s),
+                );
+                Some(UnitTimes {
+                    start: TimeValue::Samples(start),
+                    end: TimeValue::Samples(end),
+                    duration: TimeValue::Samples(end - start),
+                    local_start: TimeValue::Samples(local_start.max(0)),
+                    local_end: TimeValue::Samples(local_end.max(0)),
+                })
+            }
+            TimeUnit::Seconds => {
+                let (start, end) = (round_micros(start_s), round_micros(end_s));
+                Some(UnitTimes {
+                    start: TimeValue::Seconds(start),
+                    end: TimeValue::Seconds(end),
+                    duration: TimeValue::Seconds(round_micros(end - start)),
+                    local_start: TimeValue::Seconds(
+                        round_micros(start_s + local_offset_s).max(0.0),
+                    ),
+                    local_end: TimeValue::Seconds(round_micros(end_s + local_offset_s).max(0.0)),
+                })
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(start_s: f64, end_s: f64, window_start_ms: i64) -> Track {
+        let (start_ms, end_ms) = (
+            (start_s * 1000.0).round() as i64,
+            (end_s * 1000.0).round() as i64,
+        );
+        Track {
+            speaker_id: "edge_spk_1".to_string(),
+            start_ms,
+            end_ms,
+            duration_ms: end_ms - start_ms,
+            local_start_ms: start_ms - window_start_ms,
+            local_end_ms: end_ms - window_start_ms,
+            exact_start_s: start_s,
+            exact_end_s: end_s,
+            unit_times: None,
+            metadata: None,
+        }
+    }
+
+    fn times(unit: TimeUnit, start_s: f64, end_s: f64, window_start_ms: i64) -> UnitTimes {
+        let mut tracks = [track(start_s, end_s, window_start_ms)];
+        apply(&mut tracks, unit, 16_000);
+        tracks[0].unit_times.clone().expect("unit times set")
+    }
+
+    fn samples(value: TimeValue) -> i64 {
+        match value {
+            TimeValue::Samples(samples) => samples,
+            TimeValue::Seconds(_) => panic!("expected samples"),
+        }
+    }
+
+    fn seconds(value: TimeValue) -> f64 {
+        match value {
+            TimeValue::Seconds(seconds) => seconds,
+            TimeValue::Samples(_) => panic!("expected seconds"),
+        }
+    }
+
+    #[test]
+    fn milliseconds_add_no_fields() {
+        let mut tracks = [track(1.0, 2.0, 0)];
+        apply(&mut tracks, TimeUnit::Ms, 16_000);
+        assert!(tracks[0].unit_times.is_none());
+    }
+
+    #[test]
+    fn samples_come_from_unrounded_bounds() {
+        // 1.00003 s is 16000.48 samples, while its 1000 ms would give 16000.
+        let times = times(TimeUnit::Samples, 1.00003, 2.0000625, 0);
+        assert_eq!(samples(times.start), 16_000);
+        // 32000.5 samples, a tie rounded away from zero.
+        assert_eq!(samples(times.end), 32_001);
+        assert_eq!(samples(times.duration), 16_001);
+    }
+
+    #[test]
+    fn local_times_keep_the_window_offset() {
+        let times = times(TimeUnit::Samples, 61.5, 62.25, 60_000);
+        assert_eq!(samples(times.local_start), 24_000);
+        assert_eq!(samples(times.local_end), 36_000);
+    }
+
+    #[test]
+    fn seconds_round_to_the_microsecond() {
+        let times = times(TimeUnit::Seconds, 0.1234564, 0.9876545, 0);
+        assert_eq!(seconds(times.start), 0.123456);
+        assert_eq!(seconds(times.end), 0.987655);
+        assert!((seconds(times.start) + seconds(times.duration) - seconds(times.end)).abs() < 1e-9);
+    }
+}
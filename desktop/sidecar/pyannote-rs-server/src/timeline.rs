@@ -0,0 +1,161 @@
+use crate::track::Track;
+
+/// Embedding of one assigned segment, kept so that deleting a time range can
+/// also remove the voice data that range contributed to a speaker.
+#[derive(Debug, Clone)]
+pub struct StoredEmbedding {
+    pub speaker_id: usize,
+    pub start_ms: i64,
+    pub end_ms: i64,
+    pub embedding: Vec<f32>,
+}
+
+#[derive(Debug, Default)]
+pub struct RangeRemoval {
+    pub tracks_removed: usize,
+    pub tracks_trimmed: usize,
+    pub speech_removed_ms: i64,
+}
+
+pub fn overlaps(start_ms: i64, end_ms: i64, from_ms: i64, to_ms: i64) -> bool {
+    start_ms < to_ms && end_ms > from_ms
+}
+
+fn slice_track(track: &Track, start_ms: i64, end_ms: i64) -> Track {
+    let local_offset = track.local_start_ms - track.start_ms;
+    Track {
+        start_ms,
+        end_ms,
+        duration_ms: end_ms - start_ms,
+        local_start_ms: start_ms + local_offset,
+        local_end_ms: end_ms + local_offset,
+        exact_start_s: track.exact_start_s.max(start_ms as f64 / 1000.0),
+        exact_end_s: track.exact_end_s.min(end_ms as f64 / 1000.0),
+        ..track.clone()
+    }
+}
+
+/// Cuts `[from_ms, to_ms)` out of the timeline. Tracks straddling an edge are
+/// trimmed to the part outside the range; a track spanning the whole range is
+/// split in two.
+pub fn remove_range(timeline: &mut Vec<Track>, from_ms: i64, to_ms: i64) -> RangeRemoval {
+    let mut removal = RangeRemoval::default();
+    let mut kept = Vec::with_capacity(timeline.len());
+
+    for track in timeline.drain(..) {
+        if !overlaps(track.start_ms, track.end_ms, from_ms, to_ms) {
+            kept.push(track);
+            continue;
+        }
+
+        removal.speech_removed_ms += track.end_ms.min(to_ms) - track.start_ms.max(from_ms);
+        let head = (track.start_ms < from_ms).then(|| slice_track(&track, track.start_ms, from_ms));
+        let tail = (track.end_ms > to_ms).then(|| slice_track(&track, to_ms, track.end_ms));
+        if head.is_none() && tail.is_none() {
+            removal.tracks_removed += 1;
+        } else {
+            removal.tracks_trimmed += 1;
+        }
+        kept.extend(head);
+        kept.extend(tail);
+    }
+
+    *timeline = kept;
+    removal
+}
+
+/// Hands the part of `from`'s speech inside `[from_ms, to_ms)` to `to`,
+/// splitting tracks at the range edges. Adjacent tracks are not merged.
+pub fn relabel_range(timeline: &mut Vec<Track>, from_ms: i64, to_ms: i64, from: &str, to: &str) {
+    let mut relabeled = Vec::with_capacity(timeline.len());
+    for track in timeline.drain(..) {
+        if track.speaker_id != from || !overlaps(track.start_ms, track.end_ms, from_ms, to_ms) {
+            relabeled.push(track);
+            continue;
+        }
+
+        let head = (track.start_ms < from_ms).then(|| slice_track(&track, track.start_ms, from_ms));
+        let tail = (track.end_ms > to_ms).then(|| slice_track(&track, to_ms, track.end_ms));
+        let mut middle = slice_track(&track, track.start_ms.max(from_ms), track.end_ms.min(to_ms));
+        middle.speaker_id = to.to_string();
+        relabeled.extend(head);
+        relabeled.push(middle);
+        relabeled.extend(tail);
+    }
+    *timeline = relabeled;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spans(timeline: &[Track]) -> Vec<(&str, i64, i64)> {
+        timeline
+            .iter()
+            .map(|track| (track.speaker_id.as_str(), track.start_ms, track.end_ms))
+            .collect()
+    }
+
+    #[test]
+    fn remove_range_trims_splits_and_removes() {
+        let mut timeline = vec![
+            Track::test("edge_spk_1", 0, 2000),
+            Track::test("edge_spk_2", 2500, 3500),
+            Track::test("edge_spk_1", 4000, 6000),
+            Track::test("edge_spk_2", 7000, 10000),
+        ];
+        let removal = remove_range(&mut timeline, 1000, 5000);
+        assert_eq!(removal.tracks_removed, 1);
+        assert_eq!(removal.tracks_trimmed, 2);
+        assert_eq!(removal.speech_removed_ms, 1000 + 1000 + 1000);
+        assert_eq!(
+            spans(&timeline),
+            [
+                ("edge_spk_1", 0, 1000),
+                ("edge_spk_1", 5000, 6000),
+                ("edge_spk_2", 7000, 10000),
+            ]
+        );
+        assert_eq!(timeline[1].duration_ms, 1000);
+        assert_eq!(timeline[1].local_start_ms, 5000);
+
+        let removal = remove_range(&mut timeline, 8000, 9000);
+        assert_eq!(removal.tracks_trimmed, 1);
+        assert_eq!(
+            spans(&timeline)[2..],
+            [("edge_spk_2", 7000, 8000), ("edge_spk_2", 9000, 10000)]
+        );
+    }
+
+    #[test]
+    fn remove_range_leaves_tracks_touching_its_edges() {
+        let mut timeline = vec![
+            Track::test("edge_spk_1", 0, 1000),
+            Track::test("edge_spk_2", 2000, 3000),
+        ];
+        let removal = remove_range(&mut timeline, 1000, 2000);
+        assert_eq!(removal.tracks_removed + removal.tracks_trimmed, 0);
+        assert_eq!(removal.speech_removed_ms, 0);
+        assert_eq!(timeline.len(), 2);
+    }
+
+    #[test]
+    fn relabel_range_hands_over_only_the_range() {
+        let mut timeline = vec![
+            Track::test("edge_spk_1", 0, 4000),
+            Track::test("edge_spk_2", 1000, 2000),
+            Track::test("edge_spk_1", 4000, 5000),
+        ];
+        relabel_range(&mut timeline, 1000, 3000, "edge_spk_1", "edge_spk_3");
+        assert_eq!(
+            spans(&timeline),
+            [
+                ("edge_spk_1", 0, 1000),
+                ("edge_spk_3", 1000, 3000),
+                ("edge_spk_1", 3000, 4000),
+                ("edge_spk_2", 1000, 2000),
+                ("edge_spk_1", 4000, 5000),
+            ]
+        );
+    }
+}
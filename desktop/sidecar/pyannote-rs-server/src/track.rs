@@ -0,0 +1,124 @@
+use pyannote_rs::Segment;
+use serde::Serialize;
+
+use crate::timebase;
+
+/// Speaker id carried by tracks produced without embeddings when a latency
+/// budget forces the VAD-only fallback.
+pub const UNATTRIBUTED_SPEAKER_ID: &str = "unattributed";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Track {
+    pub speaker_id: String,
+    pub start_ms: i64,
+    pub end_ms: i64,
+    pub duration_ms: i64,
+    pub local_start_ms: i64,
+    pub local_end_ms: i64,
+    /// Session-time bounds in unrounded seconds, kept for `time_unit`
+    /// conversions that must not go through the rounded ms fields.
+    #[serde(skip)]
+    pub exact_start_s: f64,
+    #[serde(skip)]
+    pub exact_end_s: f64,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub unit_times: Option<timebase::UnitTimes>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Value>,
+}
+
+#[cfg(test)]
+impl Track {
+    /// Session track in the first window, for unit tests.
+    pub fn test(speaker_id: &str, start_ms: i64, end_ms: i64) -> Self {
+        Self {
+            speaker_id: speaker_id.to_string(),
+            start_ms,
+            end_ms,
+            duration_ms: end_ms - start_ms,
+            local_start_ms: start_ms,
+            local_end_ms: end_ms,
+            exact_start_s: start_ms as f64 / 1000.0,
+            exact_end_s: end_ms as f64 / 1000.0,
+            unit_times: None,
+            metadata: None,
+        }
+    }
+}
+
+/// Gap the built-in merge step and the session timeline use to join tracks.
+pub const DEFAULT_MERGE_GAP_MS: i64 = 250;
+
+pub fn merge_adjacent_tracks(mut tracks: Vec<Track>, max_gap_ms: i64) -> Vec<Track> {
+    if tracks.len() <= 1 {
+        return tracks;
+    }
+
+    tracks.sort_by(|a, b| a.start_ms.cmp(&b.start_ms).then(a.end_ms.cmp(&b.end_ms)));
+    let mut merged: Vec<Track> = Vec::with_capacity(tracks.len());
+
+    for current in tracks {
+        if let Some(last) = merged.last_mut() {
+            let same_speaker = last.speaker_id == current.speaker_id;
+            let gap = current.start_ms - last.end_ms;
+            if same_speaker && gap <= max_gap_ms {
+                last.end_ms = last.end_ms.max(current.end_ms);
+                last.local_end_ms = last.local_end_ms.max(current.local_end_ms);
+                last.exact_end_s = last.exact_end_s.max(current.exact_end_s);
+                last.duration_ms = (last.end_ms - last.start_ms).max(0);
+                continue;
+            }
+        }
+        merged.push(current);
+    }
+
+    merged
+}
+
+pub fn map_segment_to_track(
+    segment: &Segment,
+    window_start_ms: i64,
+    window_end_ms: i64,
+    speaker_id: usize,
+) -> Track {
+    let mut local_start_ms = (segment.start * 1000.0).round() as i64;
+    let mut local_end_ms = (segment.end * 1000.0).round() as i64;
+
+    if local_end_ms < local_start_ms {
+        std::mem::swap(&mut local_start_ms, &mut local_end_ms);
+    }
+
+    let mut start_ms = window_start_ms + local_start_ms;
+    let mut end_ms = window_start_ms + local_end_ms;
+
+    if end_ms < start_ms {
+        std::mem::swap(&mut start_ms, &mut end_ms);
+    }
+
+    start_ms = start_ms.max(window_start_ms);
+    end_ms = end_ms.min(window_end_ms).max(start_ms);
+
+    let window_start_s = window_start_ms as f64 / 1000.0;
+    let window_end_s = window_end_ms as f64 / 1000.0;
+    let exact_start_s = (window_start_s + segment.start.min(segment.end)).max(window_start_s);
+    let exact_end_s = (window_start_s + segment.start.max(segment.end))
+        .min(window_end_s)
+        .max(exact_start_s);
+
+    Track {
+        speaker_id: format!("edge_spk_{speaker_id}"),
+        start_ms,
+        end_ms,
+        duration_ms: (end_ms - start_ms).max(0),
+        local_start_ms: local_start_ms.max(0),
+        local_end_ms: local_end_ms.max(local_start_ms.max(0)),
+        exact_start_s,
+        exact_end_s,
+        unit_times: None,
+        metadata: None,
+    }
+}
+
+pub fn parse_speaker_id(raw: &str) -> Option<usize> {
+    raw.strip_prefix("edge_spk_").unwrap_or(raw).parse().ok()
+}
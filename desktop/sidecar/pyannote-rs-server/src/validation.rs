@@ -0,0 +1,410 @@
+use std::sync::Arc;
+
+use axum::body::{Body, Bytes};
+use axum::extract::{FromRequest, Request};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::error::AppError;
+use crate::handlers::calibrate::{CalibrateRequest, EstimateSpeakersRequest};
+use crate::handlers::diarize::DiarizeRequest;
+use crate::handlers::offline::OfflineDiarizeRequest;
+use crate::handlers::sessions::{ReclusterRequest, SummaryRequest};
+use crate::handlers::status::ModelReloadRequest;
+use crate::state::ServerState;
+
+/// Largest disagreement tolerated between a window's `start_end_ms` span and
+/// the length of its audio.
+const SPAN_TOLERANCE_MS: i64 = 20;
+
+#[derive(Debug, Serialize)]
+pub struct FieldError {
+    /// Path into the request body, e.g. `snippets[2].label`.
+    pub path: String,
+    pub message: String,
+}
+
+#[derive(Debug, Default)]
+pub struct Violations(Vec<FieldError>);
+
+impl Violations {
+    pub fn add(&mut self, path: impl Into<String>, message: impl Into<String>) {
+        self.0.push(FieldError {
+            path: path.into(),
+            message: message.into(),
+        });
+    }
+
+    fn unit_interval(&mut self, path: &str, value: Option<f32>) {
+        if value.is_some_and(|value| !(0.0..=1.0).contains(&value)) {
+            self.add(path, "must be a finite number in [0, 1]");
+        }
+    }
+
+    fn positive(&mut self, path: &str, value: Option<u64>) {
+        if value == Some(0) {
+            self.add(path, "must be positive");
+        }
+    }
+}
+
+/// Semantic checks that only run in strict mode, on top of the field checks
+/// the handlers already do. Lenient mode keeps clamping and defaulting.
+pub trait Validate {
+    fn validate(&self, _violations: &mut Violations) {}
+}
+
+impl Validate for DiarizeRequest {
+    fn validate(&self, violations: &mut Violations) {
+        violations.unit_interval("threshold", self.threshold);
+        violations.positive("max_speakers", self.max_speakers.map(|value| value as u64));
+        violations.positive("sample_rate", self.sample_rate.map(u64::from));
+
+        if self.idempotency_key.is_some() && self.session_id.is_none() {
+            violations.add(
+                "idempotency_key",
+                "requires session_id; a retry without one would start a new session",
+            );
+        }
+
+        if let Some([start_ms, end_ms]) = self.start_end_ms {
+            if end_ms < start_ms {
+                violations.add("start_end_ms", "end must not precede start");
+            } else {
+                let sample_rate = self.sample_rate.unwrap_or(16_000).max(1) as i64;
+                let audio_ms = base64_len(&self.content_b64) as i64 / 2 * 1000 / sample_rate;
+                if (end_ms - start_ms - audio_ms).abs() > SPAN_TOLERANCE_MS {
+                    violations.add(
+                        "start_end_ms",
+                        format!(
+                            "spans {}ms but content_b64 holds {audio_ms}ms of audio",
+                            end_ms - start_ms
+                        ),
+                    );
+                }
+            }
+        }
+    }
+}
+
+impl Validate for OfflineDiarizeRequest {
+    fn validate(&self, violations: &mut Violations) {
+        violations.unit_interval("threshold", self.threshold);
+        violations.positive("max_speakers", self.max_speakers.map(|value| value as u64));
+        violations.positive("sample_rate", self.sample_rate.map(u64::from));
+    }
+}
+
+impl Validate for EstimateSpeakersRequest {
+    fn validate(&self, violations: &mut Violations) {
+        violations.positive("max_speakers", self.max_speakers.map(|value| value as u64));
+        violations.positive("sample_rate", self.sample_rate.map(u64::from));
+    }
+}
+
+impl Validate for CalibrateRequest {
+    fn validate(&self, violations: &mut Violations) {
+        violations.unit_interval("threshold_from", self.threshold_from);
+        violations.unit_interval("threshold_to", self.threshold_to);
+        violations.positive("max_speakers", self.max_speakers.map(|value| value as u64));
+    }
+}
+
+impl Validate for ReclusterRequest {
+    fn validate(&self, violations: &mut Violations) {
+        violations.unit_interval("threshold", self.threshold);
+        violations.positive("max_speakers", self.max_speakers.map(|value| value as u64));
+    }
+}
+
+impl Validate for SummaryRequest {
+    fn validate(&self, violations: &mut Violations) {
+        if self.min_silence_ms.is_some_and(|value| value <= 0) {
+            violations.add("min_silence_ms", "must be positive");
+        }
+        if self
+            .interviewer
+            .as_deref()
+            .is_some_and(|id| id.trim().is_empty())
+        {
+            violations.add("interviewer", "must not be empty");
+        }
+    }
+}
+
+impl Validate for ModelReloadRequest {}
+
+/// Decoded length of a base64 payload, without decoding it.
+fn base64_len(content_b64: &str) -> usize {
+    content_b64.trim_end_matches('=').len() * 3 / 4
+}
+
+/// JSON body extractor that honours `--strict-validation`. Lenient mode is
+/// plain `Json`: unknown fields are ignored. Strict mode rejects unknown
+/// fields, type errors and `Validate` violations with a 422 listing every
+/// offending field path:
+///
+/// `{"detail": "request failed strict validation", "errors": [{"path": "max_speaker", "message": "unknown field"}]}`
+#[derive(Debug)]
+pub struct ValidatedJson<T>(pub T);
+
+impl<T> FromRequest<Arc<ServerState>> for ValidatedJson<T>
+where
+    T: DeserializeOwned + Validate,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &Arc<ServerState>) -> Result<Self, Self::Rejection> {
+        Self::extract(req, state.config.strict_validation).await
+    }
+}
+
+impl<T> ValidatedJson<T>
+where
+    T: DeserializeOwned + Validate,
+{
+    async fn extract(req: Request, strict: bool) -> Result<Self, Response> {
+        if !strict {
+            let Json(value) = Json::<T>::from_request(req, &())
+                .await
+                .map_err(IntoResponse::into_response)?;
+            return Ok(Self(value));
+        }
+
+        if !is_json(req.headers()) {
+            return Err(AppError {
+                status: StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                message: "expected request with `Content-Type: application/json`".to_string(),
+            }
+            .into_response());
+        }
+        let body = Bytes::from_request(req, &())
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+        let mut violations = Violations::default();
+        let mut unknown = Vec::new();
+        let mut track_unknown = |path: serde_ignored::Path<'_>| unknown.push(field_path(&path));
+        let mut deserializer = serde_json::Deserializer::from_slice(&body);
+        let parsed = serde_path_to_error::deserialize(serde_ignored::Deserializer::new(
+            &mut deserializer,
+            &mut track_unknown,
+        ));
+        let value = match parsed.map(|value: T| (value, deserializer.end())) {
+            Ok((value, Ok(()))) => Some(value),
+            Ok((_, Err(error))) => {
+                violations.add(".", error.to_string());
+                None
+            }
+            Err(error) => {
+                violations.add(error.path().to_string(), error.into_inner().to_string());
+                None
+            }
+        };
+        for path in unknown {
+            violations.add(path, "unknown field");
+        }
+        if let Some(value) = &value {
+            value.validate(&mut violations);
+        }
+
+        match value {
+            Some(value) if violations.0.is_empty() => Ok(Self(value)),
+            _ => Err(rejection(violations)),
+        }
+    }
+
+    async fn extract_optional(req: Request, strict: bool) -> Result<Option<Self>, Response> {
+        let (parts, body) = req.into_parts();
+        let body = Bytes::from_request(Request::from_parts(parts.clone(), body), &())
+            .await
+            .map_err(IntoResponse::into_response)?;
+        if body.iter().all(u8::is_ascii_whitespace) {
+            return Ok(None);
+        }
+        let req = Request::from_parts(parts, Body::from(body));
+        Self::extract(req, strict).await.map(Some)
+    }
+}
+
+/// `Option<ValidatedJson<T>>` is `None` for a request without a body, for
+/// endpoints whose fields are all optional. A body that is present is
+/// extracted and validated as usual.
+impl<T> axum::extract::OptionalFromRequest<Arc<ServerState>> for ValidatedJson<T>
+where
+    T: DeserializeOwned + Validate,
+{
+    type Rejection = Response;
+
+    async fn from_request(
+        req: Request,
+        state: &Arc<ServerState>,
+    ) -> Result<Option<Self>, Self::Rejection> {
+        Self::extract_optional(req, state.config.strict_validation).await
+    }
+}
+
+/// Renders an ignored field's path the way `serde_path_to_error` renders
+/// type errors, so both kinds of violation read alike.
+fn field_path(path: &serde_ignored::Path<'_>) -> String {
+    use serde_ignored::Path;
+    match path {
+        Path::Root => String::new(),
+        Path::Seq { parent, index } => format!("{}[{index}]", field_path(parent)),
+        Path::Map { parent, key } => match field_path(parent) {
+            parent if parent.is_empty() => key.clone(),
+            parent => format!("{parent}.{key}"),
+        },
+        Path::Some { parent }
+        | Path::NewtypeStruct { parent }
+        | Path::NewtypeVariant { parent } => field_path(parent),
+    }
+}
+
+fn is_json(headers: &HeaderMap) -> bool {
+    let Some(content_type) = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return false;
+    };
+    let mime = content_type.split(';').next().unwrap_or_default().trim();
+    mime.eq_ignore_ascii_case("application/json")
+        || (mime.starts_with("application/") && mime.ends_with("+json"))
+}
+
+fn rejection(violations: Violations) -> Response {
+    let payload = serde_json::json!({
+        "detail": "request failed strict validation",
+        "errors": violations.0,
+    });
+    (StatusCode::UNPROCESSABLE_ENTITY, Json(payload)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(body: &str) -> Request {
+        Request::builder()
+            .method("POST")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    async fn rejected<T>(body: &str) -> (StatusCode, serde_json::Value)
+    where
+        T: DeserializeOwned + Validate + std::fmt::Debug,
+    {
+        let response = ValidatedJson::<T>::extract(request(body), true)
+            .await
+            .unwrap_err();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    fn paths(payload: &serde_json::Value) -> Vec<&str> {
+        payload["errors"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|error| error["path"].as_str().unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn unknown_fields_only_fail_strict_mode() {
+        let body = r#"{"min_silence_ms": 500, "min_silence": 500}"#;
+        let ValidatedJson(lenient) = ValidatedJson::<SummaryRequest>::extract(request(body), false)
+            .await
+            .unwrap();
+        assert_eq!(lenient.min_silence_ms, Some(500));
+
+        let (status, payload) = rejected::<SummaryRequest>(body).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(payload["detail"], "request failed strict validation");
+        assert_eq!(paths(&payload), ["min_silence"]);
+    }
+
+    #[tokio::test]
+    async fn violations_name_nested_field_paths() {
+        let body = r#"{"snippets": [
+            {"content_b64": "", "label": "a"},
+            {"content_b64": "", "label": "b", "lable": "b"}
+        ], "threshold_from": 1.5}"#;
+        let (_, payload) = rejected::<CalibrateRequest>(body).await;
+        assert_eq!(paths(&payload), ["snippets[1].lable", "threshold_from"]);
+
+        let (status, payload) =
+            rejected::<SummaryRequest>(r#"{"labels": {"edge_spk_1": 5}}"#).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(paths(&payload), ["labels.edge_spk_1"]);
+    }
+
+    #[tokio::test]
+    async fn conflicting_window_parameters_are_rejected() {
+        // 3200 bytes of base64 hold 2400 bytes, 75 ms of 16 kHz audio.
+        let content_b64 = "A".repeat(3200);
+        let body = format!(
+            r#"{{"content_b64": "{content_b64}", "idempotency_key": "k1", "start_end_ms": [0, 1000]}}"#
+        );
+        let (_, payload) = rejected::<DiarizeRequest>(&body).await;
+        assert_eq!(paths(&payload), ["idempotency_key", "start_end_ms"]);
+
+        let body = format!(
+            r#"{{"content_b64": "{content_b64}", "session_id": "s", "idempotency_key": "k1", "start_end_ms": [1000, 1075]}}"#
+        );
+        assert!(
+            ValidatedJson::<DiarizeRequest>::extract(request(&body), true)
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn strict_mode_requires_a_json_content_type() {
+        let req = Request::builder()
+            .method("POST")
+            .body(Body::from("{}"))
+            .unwrap();
+        let response = ValidatedJson::<SummaryRequest>::extract(req, true)
+            .await
+            .unwrap_err();
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    #[tokio::test]
+    async fn a_missing_body_is_none() {
+        for strict in [false, true] {
+            for body in ["", " \n"] {
+                let extracted =
+                    ValidatedJson::<SummaryRequest>::extract_optional(request(body), strict).await;
+                assert!(extracted.unwrap().is_none());
+            }
+            let extracted = ValidatedJson::<SummaryRequest>::extract_optional(
+                request(r#"{"interviewer": "edge_spk_2"}"#),
+                strict,
+            )
+            .await;
+            let ValidatedJson(summary) = extracted.unwrap().unwrap();
+            assert_eq!(summary.interviewer.as_deref(), Some("edge_spk_2"));
+        }
+
+        let extracted = ValidatedJson::<SummaryRequest>::extract_optional(
+            request(r#"{"interviewer": ""}"#),
+            true,
+        )
+        .await;
+        assert_eq!(
+            extracted.unwrap_err().status(),
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+    }
+}